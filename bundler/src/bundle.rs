@@ -1,22 +1,29 @@
-use flate2::{write::GzEncoder, Compression};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
 use schemars::{schema::RootSchema, schema_for, JsonSchema};
 use serde::Serialize;
-use sqlx::MySqlPool;
+use sha2::{Digest, Sha256};
 use std::{
-    collections::{hash_map::DefaultHasher, BTreeMap, HashMap},
-    ffi::OsStr,
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
     fmt::Debug,
-    hash::{Hash, Hasher},
+    io::{BufWriter, Read, Write},
+    path::PathBuf,
+    sync::Arc,
 };
 use tar::Header;
-use tokio::try_join;
-use tracing::{instrument, trace};
+use tracing::instrument;
 
 use crate::{
+    delta::Patch,
+    filter::FieldFilters,
+    layout::{BundleLayout, LayoutError},
     permissionables::{
-        beamlines::Beamlines, proposals::Proposals, sessions::Sessions, subjects::Subjects,
+        aliases::Aliases, beamlines::Beamlines, proposals::Proposals, sessions::Sessions,
+        subjects::Subjects, visits::Visits, Permissionables,
     },
-    StaticDataGlob,
+    signing::{BundleSigner, FileDigest, SIGNATURES_FILE},
+    source::PermissionablesSource,
+    static_data::{StaticDataError, StaticDataFiles},
 };
 
 /// A compiled Web Assembly module
@@ -29,7 +36,7 @@ struct WasmModule {
 }
 
 /// A placeholder to be used when no metadata is required
-#[derive(Debug, Hash, Serialize)]
+#[derive(Debug, Serialize)]
 pub struct NoMetadata;
 
 /// The manifest file, which contains data about the bundle and optional additonal metadata
@@ -38,7 +45,7 @@ struct Manifest<Metadata>
 where
     Metadata: Serialize,
 {
-    /// The revision of the bundle, comprising of the crate version number and the bundle hash
+    /// The revision of the bundle, a digest of the bundle contents
     revision: String,
     /// The directory prefixes of the data contained within the bundle
     roots: Vec<String>,
@@ -70,82 +77,139 @@ where
 {
     /// The manifest file, which contains data about the bundle and optional additonal metadata
     manifest: Manifest<Metadata>,
-    /// A mapping of subjects to their various attributes
-    subjects: Subjects,
-    /// A mapping of sessions to their various attributes
-    sessions: Sessions,
-    /// A mapping of proposals to their various attributes
-    proposals: Proposals,
-    /// A mapping of beamlines to their various attributes
-    beamlines: Beamlines,
+    /// The subjects, sessions, proposals and beamlines with their various attributes, which may
+    /// be shared between bundles
+    permissionables: Arc<Permissionables>,
     /// A map (name to data) of static files to include in the bundle
-    static_data: HashMap<String, Vec<u8>>,
+    static_data: BTreeMap<String, Vec<u8>>,
+    /// The permissionables to include, the prefix to place them beneath and the filters to apply
+    options: BundleOptions,
+}
+
+/// The default prefix applied to data files in the bundle. Open Policy Agent does not support loading bundles with overlapping roots
+pub const BUNDLE_PREFIX: &str = "diamond/data";
+
+/// The names of the permissionable data documents which may be included in a bundle
+pub const PERMISSIONABLES: [&str; 6] = [
+    "subjects",
+    "sessions",
+    "proposals",
+    "beamlines",
+    "visits",
+    "aliases",
+];
+
+/// A data document of a [`Bundle`], which is serialized only once it is needed
+#[derive(Debug, Clone, Copy)]
+enum DataDocument<'a> {
+    /// The subjects permissionable
+    Subjects,
+    /// The sessions permissionable
+    Sessions,
+    /// The proposals permissionable
+    Proposals,
+    /// The beamlines permissionable
+    Beamlines,
+    /// The visits permissionable, derived from the sessions
+    Visits,
+    /// The aliases permissionable, derived from the subjects
+    Aliases,
+    /// A static data file, which is already serialized
+    Static(&'a [u8]),
+}
+
+/// The contents and layout of a [`Bundle`]
+#[derive(Debug, Clone)]
+pub struct BundleOptions {
+    /// The placement of data documents within the bundle
+    pub layout: BundleLayout,
+    /// The names of the permissionable data documents to include
+    pub permissionables: BTreeSet<String>,
+    /// The fields of the permissionables to be omitted or hashed when written
+    pub field_filters: FieldFilters,
+}
+
+impl BundleOptions {
+    /// Creates [`BundleOptions`] which include every permissionable in the given layout
+    pub fn new(layout: BundleLayout, field_filters: FieldFilters) -> Self {
+        Self {
+            layout,
+            permissionables: PERMISSIONABLES.map(String::from).into(),
+            field_filters,
+        }
+    }
+
+    /// Checks that the data documents and roots of the included permissionables do not overlap
+    pub fn validate(&self) -> Result<(), LayoutError> {
+        self.layout.validate(&self.permissionables)
+    }
 }
 
-/// The prefix applied to data files in the bundle. Open Policy Agent does not support loading bundles with overlapping prefixes
-const BUNDLE_PREFIX: &str = "diamond/data";
+impl Default for BundleOptions {
+    fn default() -> Self {
+        Self::new(BundleLayout::default(), FieldFilters::default())
+    }
+}
 
 impl<Metadata> Bundle<Metadata>
 where
-    Metadata: Debug + Hash + Serialize,
+    Metadata: Debug + Serialize,
 {
-    /// Creates a [`Bundle`] from known [`Subjects`]
+    /// Creates a [`Bundle`] from known [`Permissionables`], checking that no static data would
+    /// be placed over them
     pub fn new(
         metadata: Metadata,
-        subjects: Subjects,
-        sessions: Sessions,
-        proposals: Proposals,
-        beamlines: Beamlines,
-        static_data: HashMap<String, Vec<u8>>,
-    ) -> Self {
-        let mut hasher = DefaultHasher::new();
-        metadata.hash(&mut hasher);
-        subjects.hash(&mut hasher);
-        sessions.hash(&mut hasher);
-        proposals.hash(&mut hasher);
-        beamlines.hash(&mut hasher);
-        for entry in &static_data {
-            entry.hash(&mut hasher);
-        }
-        let hash = hasher.finish();
-
-        Self {
+        permissionables: Arc<Permissionables>,
+        static_data: BTreeMap<String, Vec<u8>>,
+        options: BundleOptions,
+    ) -> Result<Self, BundleDataError> {
+        options.layout.validate_static_data(
+            &options.permissionables,
+            static_data.keys().map(String::as_str),
+        )?;
+        let mut bundle = Self {
             manifest: Manifest {
-                revision: format!("{}:{}", crate::built_info::PKG_VERSION, hash),
-                roots: vec![BUNDLE_PREFIX.to_string()],
+                revision: String::new(),
+                roots: options.layout.roots(&options.permissionables),
                 wasm: vec![],
                 metadata,
             },
-            subjects,
-            sessions,
-            proposals,
-            beamlines,
+            permissionables,
             static_data,
+            options,
+        };
+        bundle.manifest.revision = bundle.digest()?;
+        Ok(bundle)
+    }
+
+    /// Computes the SHA-256 digest of the serialized [`Bundle`] contents, in a canonical order,
+    /// such that identical data always produces an identical revision
+    fn digest(&self) -> Result<String, serde_json::Error> {
+        let mut hasher = Sha256::new();
+        let manifest = serde_json::to_vec(&(&self.manifest.roots, &self.manifest.metadata))?;
+        hasher.update((manifest.len() as u64).to_be_bytes());
+        hasher.update(manifest);
+        for (name, document) in self.data_documents() {
+            let mut document_hasher = Sha256::new();
+            self.serialize_into(document, &mut document_hasher)?;
+            hasher.update((name.len() as u64).to_be_bytes());
+            hasher.update(name);
+            hasher.update(document_hasher.finalize());
         }
+        Ok(hex::encode(hasher.finalize()))
     }
 
-    /// Fetches [`Subjects`] from ISPyB and constructs a [`Bundle`]
+    /// Fetches [`Permissionables`] from the [`PermissionablesSource`] and constructs a [`Bundle`]
     #[instrument(name = "fetch_bundle")]
     pub async fn fetch(
         metadata: Metadata,
-        static_data: &[StaticDataGlob],
-        ispyb_pool: &MySqlPool,
-    ) -> Result<Self, sqlx::Error> {
-        let (subjects, sessions, proposals, beamlines) = try_join!(
-            Subjects::fetch(ispyb_pool),
-            Sessions::fetch(ispyb_pool),
-            Proposals::fetch(ispyb_pool),
-            Beamlines::fetch(ispyb_pool),
-        )?;
-        let static_data = read_static_data(static_data).await?;
-        Ok(Self::new(
-            metadata,
-            subjects,
-            sessions,
-            proposals,
-            beamlines,
-            static_data,
-        ))
+        static_data: &StaticDataFiles,
+        source: &PermissionablesSource,
+        options: &BundleOptions,
+    ) -> Result<Self, BundleDataError> {
+        let permissionables = Arc::new(source.fetch().await?);
+        let static_data = static_data.read().await?;
+        Self::new(metadata, permissionables, static_data, options.clone())
     }
 
     /// The current revision of the bundle, as recorded in the [`Manifest`]
@@ -153,98 +217,549 @@ where
         &self.manifest.revision
     }
 
-    /// Serializes the [`Bundle`] as a gzipped tar archive, for import by Open Policy Agent
-    pub fn to_tar_gz(&self) -> Result<Vec<u8>, anyhow::Error> {
-        let mut bundle_builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::best()));
+    /// The number of entries in each of the permissionables included in the [`Bundle`], keyed by
+    /// their name
+    pub fn entry_counts(&self) -> BTreeMap<String, usize> {
+        let permissionables = &self.permissionables;
+        self.options
+            .permissionables
+            .iter()
+            .filter_map(|name| {
+                let count = match name.as_str() {
+                    "subjects" => permissionables.subjects.len(),
+                    "sessions" => permissionables.sessions.len(),
+                    "proposals" => permissionables.proposals.len(),
+                    "beamlines" => permissionables.beamlines.len(),
+                    "visits" => Visits::from(permissionables.sessions.as_ref()).len(),
+                    "aliases" => Aliases::new(
+                        permissionables.subjects.as_ref(),
+                        &self.options.field_filters,
+                    )
+                    .len(),
+                    _ => return None,
+                };
+                Some((name.clone(), count))
+            })
+            .collect()
+    }
 
-        let manifest = serde_json::to_vec(&self.manifest)?;
-        let mut manifest_header = Header::from_bytes(&manifest);
-        bundle_builder.append_data(&mut manifest_header, ".manifest", manifest.as_slice())?;
-
-        let subjects = serde_json::to_vec(&self.subjects)?;
-        let mut subjects_header = Header::from_bytes(&subjects);
-        bundle_builder.append_data(
-            &mut subjects_header,
-            format!("{BUNDLE_PREFIX}/subjects/data.json"),
-            subjects.as_slice(),
-        )?;
+    /// The data documents of the [`Bundle`], keyed by the path of their directory within the bundle
+    fn data_documents(&self) -> BTreeMap<String, DataDocument<'_>> {
+        let mut documents = BTreeMap::new();
+        for name in &self.options.permissionables {
+            let document = match name.as_str() {
+                "subjects" => DataDocument::Subjects,
+                "sessions" => DataDocument::Sessions,
+                "proposals" => DataDocument::Proposals,
+                "beamlines" => DataDocument::Beamlines,
+                "visits" => DataDocument::Visits,
+                "aliases" => DataDocument::Aliases,
+                _ => continue,
+            };
+            documents.insert(self.options.layout.path(name), document);
+        }
+        for (name, data) in &self.static_data {
+            documents.insert(self.options.layout.path(name), DataDocument::Static(data));
+        }
+        documents
+    }
 
-        let sessions = serde_json::to_vec(&self.sessions)?;
-        let mut sessions_header = Header::from_bytes(&sessions);
-        bundle_builder.append_data(
-            &mut sessions_header,
-            format!("{BUNDLE_PREFIX}/sessions/data.json"),
-            sessions.as_slice(),
-        )?;
+    /// Serializes a single data document of the [`Bundle`] into the writer, through a buffer,
+    /// applying the [`FieldFilters`] to each entry of a permissionable as it is written
+    fn serialize_into(
+        &self,
+        document: DataDocument<'_>,
+        writer: impl Write,
+    ) -> Result<(), serde_json::Error> {
+        let mut writer = BufWriter::new(writer);
+        let field_filters = &self.options.field_filters;
+        let permissionables = &self.permissionables;
+        match document {
+            DataDocument::Subjects => serde_json::to_writer(
+                &mut writer,
+                &field_filters.entries("subjects", &permissionables.subjects),
+            ),
+            DataDocument::Sessions => serde_json::to_writer(
+                &mut writer,
+                &field_filters.entries("sessions", &permissionables.sessions),
+            ),
+            DataDocument::Proposals => serde_json::to_writer(
+                &mut writer,
+                &field_filters.entries("proposals", &permissionables.proposals),
+            ),
+            DataDocument::Beamlines => serde_json::to_writer(
+                &mut writer,
+                &field_filters.entries("beamlines", &permissionables.beamlines),
+            ),
+            DataDocument::Visits => serde_json::to_writer(
+                &mut writer,
+                &Visits::from(permissionables.sessions.as_ref()),
+            ),
+            DataDocument::Aliases => serde_json::to_writer(
+                &mut writer,
+                &Aliases::new(permissionables.subjects.as_ref(), field_filters),
+            ),
+            DataDocument::Static(data) => writer.write_all(data).map_err(serde_json::Error::io),
+        }?;
+        writer.flush().map_err(serde_json::Error::io)
+    }
 
-        let proposals = serde_json::to_vec(&self.proposals)?;
-        let mut proposals_header = Header::from_bytes(&proposals);
-        bundle_builder.append_data(
-            &mut proposals_header,
-            format!("{BUNDLE_PREFIX}/proposals/data.json"),
-            proposals.as_slice(),
-        )?;
+    /// Serializes a single data document of the [`Bundle`]
+    fn serialize<'a>(
+        &'a self,
+        document: DataDocument<'a>,
+    ) -> Result<Cow<'a, [u8]>, serde_json::Error> {
+        if let DataDocument::Static(data) = document {
+            return Ok(Cow::Borrowed(data));
+        }
+        let mut data = Vec::new();
+        self.serialize_into(document, &mut data)?;
+        Ok(Cow::Owned(data))
+    }
 
-        let beamlines = serde_json::to_vec(&self.beamlines)?;
-        let mut beamlines_header = Header::from_bytes(&beamlines);
-        bundle_builder.append_data(
-            &mut beamlines_header,
-            format!("{BUNDLE_PREFIX}/beamlines/data.json"),
-            beamlines.as_slice(),
-        )?;
+    /// Whether a data document is identical to one of the `base` bundle without serializing
+    /// either, as both are derived from the same shared part of the permissionables, with the
+    /// same filters, or are identical static data
+    fn shares_document(
+        &self,
+        base: &Bundle<Metadata>,
+        document: DataDocument<'_>,
+        base_document: DataDocument<'_>,
+    ) -> bool {
+        let (permissionables, base_permissionables) =
+            (&self.permissionables, &base.permissionables);
+        match (document, base_document) {
+            (DataDocument::Static(data), DataDocument::Static(base_data)) => data == base_data,
+            _ if self.options.field_filters != base.options.field_filters => false,
+            (DataDocument::Subjects, DataDocument::Subjects)
+            | (DataDocument::Aliases, DataDocument::Aliases) => {
+                Arc::ptr_eq(&permissionables.subjects, &base_permissionables.subjects)
+            }
+            (DataDocument::Sessions, DataDocument::Sessions)
+            | (DataDocument::Visits, DataDocument::Visits) => {
+                Arc::ptr_eq(&permissionables.sessions, &base_permissionables.sessions)
+            }
+            (DataDocument::Proposals, DataDocument::Proposals) => {
+                Arc::ptr_eq(&permissionables.proposals, &base_permissionables.proposals)
+            }
+            (DataDocument::Beamlines, DataDocument::Beamlines) => {
+                Arc::ptr_eq(&permissionables.beamlines, &base_permissionables.beamlines)
+            }
+            _ => false,
+        }
+    }
 
-        for (name, data) in &self.static_data {
-            let mut header = Header::from_bytes(data);
-            bundle_builder.append_data(
-                &mut header,
-                format!("{BUNDLE_PREFIX}/{name}/data.json"),
-                data.as_slice(),
+    /// Serializes each data document of the [`Bundle`] in turn, with the path of its directory
+    /// within the bundle, such that only one need be held in memory at once
+    fn serialized_documents(
+        &self,
+    ) -> impl Iterator<Item = Result<(String, Cow<'_, [u8]>), serde_json::Error>> {
+        self.data_documents()
+            .into_iter()
+            .map(|(path, document)| Ok((path, self.serialize(document)?)))
+    }
+
+    /// Passes each serialized file of the [`Bundle`] to `write` with its path within the bundle,
+    /// preceeded by their signatures if a [`BundleSigner`] is provided
+    ///
+    /// Each data document is serialized only as it is written, and once beforehand to produce its
+    /// digest when signing, such that at most one is held in memory at any time
+    pub fn write_files(
+        &self,
+        signer: Option<&BundleSigner>,
+        mut write: impl FnMut(&str, &[u8]) -> Result<(), anyhow::Error>,
+    ) -> Result<(), anyhow::Error> {
+        let manifest = serde_json::to_vec(&self.manifest)?;
+        if let Some(signer) = signer {
+            let mut digests = vec![FileDigest::new(".manifest", &manifest)?];
+            for document in self.serialized_documents() {
+                let (path, data) = document?;
+                digests.push(FileDigest::new(&format!("{path}/data.json"), &data)?);
+            }
+            write(
+                SIGNATURES_FILE,
+                &serde_json::to_vec(&signer.sign_digests(digests)?)?,
             )?;
         }
+        write(".manifest", &manifest)?;
+        for document in self.serialized_documents() {
+            let (path, data) = document?;
+            write(&format!("{path}/data.json"), &data)?;
+        }
+        Ok(())
+    }
 
-        Ok(bundle_builder.into_inner()?.finish()?)
+    /// Serializes the [`Bundle`] into a [`TarGzWriter`], for import by Open Policy Agent
+    pub fn write_tar_gz(
+        &self,
+        archive: &mut TarGzWriter,
+        signer: Option<&BundleSigner>,
+    ) -> Result<(), anyhow::Error> {
+        self.write_files(signer, |path, data| Ok(archive.append(path, data)?))
     }
 
-    /// Produces a set of schemas associated with the data in the bundle
-    pub fn schemas() -> BTreeMap<String, RootSchema> {
-        BTreeMap::from([
-            (Subjects::schema_name(), schema_for!(Subjects)),
-            (Sessions::schema_name(), schema_for!(Sessions)),
-            (Proposals::schema_name(), schema_for!(Proposals)),
-            (Beamlines::schema_name(), schema_for!(Beamlines)),
-        ])
-    }
-}
-
-/// Read static data from files that should be included in the compiled bundle
-async fn read_static_data(
-    patterns: &[StaticDataGlob],
-) -> Result<HashMap<String, Vec<u8>>, std::io::Error> {
-    let mut data = HashMap::new();
-    for pattern in patterns {
-        for file in glob::glob(pattern.as_ref()).expect("Pattern was validated by CLI") {
-            let file = file.map_err(|e| e.into_error())?;
-            trace!(glob = pattern.as_ref(), file = ?file, "Reading static data from {file:?}");
-            let name = file.file_stem();
-            let Some(name) = name.and_then(OsStr::to_str) else {
-                // Save having to think about non-utf8 in OPA rules
-                trace!("Skipping non-utf8 static file: {name:?}");
+    /// Serializes the [`Bundle`] as a gzipped tar archive, for import by Open Policy Agent
+    pub fn to_tar_gz(&self, signer: Option<&BundleSigner>) -> Result<Vec<u8>, anyhow::Error> {
+        let mut archive = TarGzWriter::default();
+        self.write_tar_gz(&mut archive, signer)?;
+        Ok(archive.finish()?)
+    }
+
+    /// Serializes delta [`Bundle`]s, each of which transforms one of the `bases` into this one, as
+    /// gzipped tar archives, for import by Open Policy Agent
+    ///
+    /// The deltas are computed together, a data document at a time, such that each document of
+    /// this bundle is serialized at most once, and not at all where it is shared with every base
+    pub fn to_delta_tar_gzs(
+        &self,
+        bases: &[&Bundle<Metadata>],
+        signer: Option<&BundleSigner>,
+    ) -> Result<Vec<Vec<u8>>, anyhow::Error> {
+        let documents = self.data_documents();
+        let base_documents = bases
+            .iter()
+            .map(|base| base.data_documents())
+            .collect::<Vec<_>>();
+        let mut patches = bases.iter().map(|_| Patch::default()).collect::<Vec<_>>();
+        for (patch, base_documents) in patches.iter_mut().zip(&base_documents) {
+            for path in base_documents
+                .keys()
+                .filter(|path| !documents.contains_key(*path))
+            {
+                patch.remove_file(path);
+            }
+        }
+        for (path, document) in &documents {
+            let changed = bases
+                .iter()
+                .zip(&base_documents)
+                .enumerate()
+                .filter_map(|(index, (base, base_documents))| {
+                    let base_document = base_documents.get(path).copied();
+                    let shared = base_document.is_some_and(|base_document| {
+                        self.shares_document(base, *document, base_document)
+                    });
+                    (!shared).then_some((index, base, base_document))
+                })
+                .collect::<Vec<_>>();
+            if changed.is_empty() {
                 continue;
-            };
-            data.insert(name.to_string(), tokio::fs::read(&file).await?);
+            }
+            let data = self.serialize(*document)?;
+            for (index, base, base_document) in changed {
+                let base_data = base_document
+                    .map(|base_document| base.serialize(base_document))
+                    .transpose()?;
+                patches[index].push_file(path, base_data.as_deref(), &data)?;
+            }
+        }
+        let manifest = serde_json::to_vec(&self.manifest)?;
+        patches
+            .into_iter()
+            .map(|patch| {
+                Ok(write_tar_gz(with_signatures(
+                    vec![
+                        (".manifest".to_string(), manifest.clone()),
+                        ("patch.json".to_string(), serde_json::to_vec(&patch)?),
+                    ],
+                    signer,
+                )?)?)
+            })
+            .collect()
+    }
+
+    /// Produces a set of schemas associated with the data in the bundle
+    pub fn schemas(field_filters: &FieldFilters) -> BTreeMap<String, RootSchema> {
+        data_schemas()
+            .into_iter()
+            .map(|(name, schema_name, mut schema)| {
+                field_filters.filter_schema(name, &mut schema);
+                (schema_name, schema)
+            })
+            .collect()
+    }
+
+    /// Produces the unfiltered schema of each permissionable, keyed by its name in the bundle
+    pub fn permissionable_schemas() -> BTreeMap<String, RootSchema> {
+        data_schemas()
+            .into_iter()
+            .map(|(name, _, schema)| (name.to_string(), schema))
+            .collect()
+    }
+}
+
+/// The schema of each permissionable data file, with its name in the bundle and schema name
+fn data_schemas() -> [(&'static str, String, RootSchema); 6] {
+    [
+        ("subjects", Subjects::schema_name(), schema_for!(Subjects)),
+        ("sessions", Sessions::schema_name(), schema_for!(Sessions)),
+        (
+            "proposals",
+            Proposals::schema_name(),
+            schema_for!(Proposals),
+        ),
+        (
+            "beamlines",
+            Beamlines::schema_name(),
+            schema_for!(Beamlines),
+        ),
+        ("visits", Visits::schema_name(), schema_for!(Visits)),
+        ("aliases", Aliases::schema_name(), schema_for!(Aliases)),
+    ]
+}
+
+/// Prepends the signatures of a set of files, keyed by their path, if a [`BundleSigner`] is
+/// provided
+fn with_signatures(
+    mut files: Vec<(String, Vec<u8>)>,
+    signer: Option<&BundleSigner>,
+) -> Result<Vec<(String, Vec<u8>)>, anyhow::Error> {
+    if let Some(signer) = signer {
+        let signatures = serde_json::to_vec(
+            &signer.sign(
+                files
+                    .iter()
+                    .map(|(path, data)| (path.as_str(), data.as_slice())),
+            )?,
+        )?;
+        files.insert(0, (SIGNATURES_FILE.to_string(), signatures));
+    }
+    Ok(files)
+}
+
+/// A gzipped tar archive to which files are compressed as they are appended
+pub struct TarGzWriter {
+    /// The archive being written
+    builder: tar::Builder<GzEncoder<Vec<u8>>>,
+    /// The total size of the files appended, before compression
+    uncompressed_size: usize,
+}
+
+impl Default for TarGzWriter {
+    fn default() -> Self {
+        Self {
+            builder: tar::Builder::new(GzEncoder::new(Vec::new(), Compression::best())),
+            uncompressed_size: 0,
         }
     }
-    Ok(data)
 }
 
-#[allow(dead_code)]
+impl TarGzWriter {
+    /// Appends a file to the archive at the given path
+    pub fn append(&mut self, path: &str, data: &[u8]) -> Result<(), std::io::Error> {
+        let mut header = Header::from_bytes(data);
+        self.builder.append_data(&mut header, path, data)?;
+        self.uncompressed_size += data.len();
+        Ok(())
+    }
+
+    /// The total size of the files appended, before compression
+    pub fn uncompressed_size(&self) -> usize {
+        self.uncompressed_size
+    }
+
+    /// Completes the archive, returning the compressed bytes
+    pub fn finish(self) -> Result<Vec<u8>, std::io::Error> {
+        self.builder.into_inner()?.finish()
+    }
+}
+
+/// Writes a set of files, keyed by their path, to a gzipped tar archive
+fn write_tar_gz(files: Vec<(String, Vec<u8>)>) -> Result<Vec<u8>, std::io::Error> {
+    let mut archive = TarGzWriter::default();
+    for (path, data) in files {
+        archive.append(&path, &data)?;
+    }
+    archive.finish()
+}
+
+/// Reads the files in a gzipped tar archive, keyed by their path
+pub fn read_tar_gz(reader: impl Read) -> Result<BTreeMap<String, Vec<u8>>, std::io::Error> {
+    let mut archive = tar::Archive::new(GzDecoder::new(reader));
+    let mut files = BTreeMap::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.to_string_lossy().into_owned();
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        files.insert(path, data);
+    }
+    Ok(files)
+}
+
 /// Combination of possible errors when fetching data to create bundle
 #[derive(Debug, thiserror::Error)]
 pub enum BundleDataError {
     /// Error fetching data from database
     #[error("Error reading dynamic data: {0}")]
     Sql(#[from] sqlx::Error),
-    /// Error fetching data from file
+    /// Error reading or parsing static data files
     #[error("Error reading static data: {0}")]
-    Static(#[from] std::io::Error),
+    Static(#[from] StaticDataError),
+    /// Error fetching data from a snapshot
+    #[error("Error reading snapshot {0}: {1}")]
+    Snapshot(PathBuf, std::io::Error),
+    /// Error reading a SQL dump to be loaded into the database
+    #[error("Error reading SQL dump {0}: {1}")]
+    SqlDump(PathBuf, std::io::Error),
+    /// A SQL dump would be loaded into a database which may be a live ISPyB
+    #[error("Refusing to load SQL dump {0} into a database which contains tables, an empty scratch database is required")]
+    NonEmptyDatabase(PathBuf),
+    /// Error serializing data to compute the revision
+    #[error("Error serializing data: {0}")]
+    Serialization(#[from] serde_json::Error),
+    /// Static data would be placed over permissionable data
+    #[error("Error placing static data: {0}")]
+    Layout(#[from] LayoutError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_tar_gz, Bundle, BundleDataError, BundleOptions, NoMetadata, TarGzWriter};
+    use crate::{layout::LayoutError, permissionables::Permissionables};
+    use serde_json::json;
+    use std::{
+        collections::{BTreeMap, BTreeSet},
+        sync::Arc,
+    };
+
+    fn bundle(static_data: &[(&str, &str)]) -> Bundle<NoMetadata> {
+        Bundle::new(
+            NoMetadata,
+            Default::default(),
+            static_data
+                .iter()
+                .map(|(name, data)| (name.to_string(), data.as_bytes().to_vec()))
+                .collect::<BTreeMap<_, _>>(),
+            Default::default(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn revision_independent_of_order() {
+        let forward = bundle(&[("admin", "{}"), ("beamlines_extra", "[]")]);
+        let reverse = bundle(&[("beamlines_extra", "[]"), ("admin", "{}")]);
+        assert_eq!(forward.revision(), reverse.revision());
+    }
+
+    #[test]
+    fn revision_depends_on_contents() {
+        let before = bundle(&[("admin", r#"{"i22_admin": ["i22"]}"#)]);
+        let after = bundle(&[("admin", r#"{"i22_admin": ["i22", "b21"]}"#)]);
+        assert_ne!(before.revision(), after.revision());
+        assert_eq!(64, before.revision().len());
+    }
+
+    #[test]
+    fn static_data_cannot_replace_permissionables() {
+        for name in ["subjects", "sessions/extra", "aliases"] {
+            assert!(matches!(
+                Bundle::new(
+                    NoMetadata,
+                    Default::default(),
+                    BTreeMap::from([(name.to_string(), b"{}".to_vec())]),
+                    Default::default(),
+                ),
+                Err(BundleDataError::Layout(LayoutError::OverlappingStaticData(
+                    _,
+                    _
+                )))
+            ));
+        }
+    }
+
+    #[test]
+    fn deltas_of_changed_documents() {
+        let permissionables = Arc::new(
+            serde_json::from_slice::<Permissionables>(
+                &std::fs::read("tests/snapshots/permissionables.json").unwrap(),
+            )
+            .unwrap(),
+        );
+        let changed = Arc::new(Permissionables {
+            beamlines: Arc::new(
+                serde_json::from_value(json!({"i12": {"sessions": [40, 41]}})).unwrap(),
+            ),
+            ..Permissionables::clone(&permissionables)
+        });
+        let bundle = |permissionables: &Arc<Permissionables>, admin: &str| {
+            Bundle::new(
+                NoMetadata,
+                permissionables.clone(),
+                BTreeMap::from([("admin".to_string(), admin.as_bytes().to_vec())]),
+                Default::default(),
+            )
+            .unwrap()
+        };
+        let base = bundle(&permissionables, r#"{"i22_admin": ["i22"]}"#);
+        let static_changed = bundle(&permissionables, r#"{"i22_admin": ["i22", "b21"]}"#);
+        let target = bundle(&changed, r#"{"i22_admin": ["i22", "b21"]}"#);
+        let patches = target
+            .to_delta_tar_gzs(&[&base, &static_changed], None)
+            .unwrap()
+            .into_iter()
+            .map(|delta| {
+                serde_json::from_slice::<serde_json::Value>(
+                    &read_tar_gz(delta.as_slice()).unwrap()["patch.json"],
+                )
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            json!({"data": [
+                {"op": "upsert", "path": "/diamond/data/admin/i22_admin", "value": ["i22", "b21"]},
+                {"op": "upsert", "path": "/diamond/data/beamlines/i12", "value": {"sessions": [40, 41]}}
+            ]}),
+            patches[0]
+        );
+        assert_eq!(
+            json!({"data": [
+                {"op": "upsert", "path": "/diamond/data/beamlines/i12", "value": {"sessions": [40, 41]}}
+            ]}),
+            patches[1]
+        );
+    }
+
+    #[test]
+    fn entry_counts_of_included_permissionables() {
+        let permissionables = Arc::new(
+            serde_json::from_slice::<Permissionables>(
+                &std::fs::read("tests/snapshots/permissionables.json").unwrap(),
+            )
+            .unwrap(),
+        );
+        let bundle = Bundle::new(
+            NoMetadata,
+            permissionables,
+            BTreeMap::new(),
+            BundleOptions {
+                permissionables: BTreeSet::from(["sessions".to_string(), "visits".to_string()]),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            BTreeMap::from([("sessions".to_string(), 1), ("visits".to_string(), 1)]),
+            bundle.entry_counts()
+        );
+    }
+
+    #[test]
+    fn archive_contains_data_files() {
+        let bundle = bundle(&[("admin", "{}")]);
+        let mut archive = TarGzWriter::default();
+        bundle.write_tar_gz(&mut archive, None).unwrap();
+        let uncompressed_size = archive.uncompressed_size();
+        let files = read_tar_gz(archive.finish().unwrap().as_slice()).unwrap();
+        assert_eq!(
+            uncompressed_size,
+            files.values().map(|data| data.len()).sum::<usize>()
+        );
+        for document in bundle.serialized_documents() {
+            let (path, data) = document.unwrap();
+            assert_eq!(*data, files[&format!("{path}/data.json")]);
+        }
+        assert!(files.contains_key(".manifest"));
+    }
 }
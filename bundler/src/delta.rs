@@ -0,0 +1,159 @@
+use serde::Serialize;
+use serde_json::value::RawValue;
+use std::collections::BTreeMap;
+
+/// The contents of the `patch.json` file in an Open Policy Agent delta bundle
+#[derive(Debug, Default, Serialize)]
+pub struct Patch {
+    /// The operations to apply to the data of the base bundle, in order
+    data: Vec<PatchOperation>,
+}
+
+/// A single JSON Patch operation, as supported by Open Policy Agent delta bundles
+#[derive(Debug, Serialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum PatchOperation {
+    /// Create or replace the value at the path
+    Upsert {
+        /// The JSON Pointer to the value
+        path: String,
+        /// The new value, as serialized in the target data file
+        value: Box<RawValue>,
+    },
+    /// Remove the value at the path
+    Remove {
+        /// The JSON Pointer to the value
+        path: String,
+    },
+}
+
+impl Patch {
+    /// Appends the operation to remove a data file which is absent from the target, given the path
+    /// of its directory within the bundle
+    ///
+    /// Removals should precede any other operations, such that they cannot undo those of files
+    /// placed beneath the removed one
+    pub fn remove_file(&mut self, path: &str) {
+        self.data.push(PatchOperation::Remove {
+            path: pointer(path, []),
+        });
+    }
+
+    /// Appends the operations required to transform a single data file of the base, or its
+    /// absence, into that of the target, given the path of its directory within the bundle
+    ///
+    /// Data files which are JSON objects in both are patched entry by entry, comparing the
+    /// serialized entries such that only those which changed are parsed, all others are replaced
+    /// whole
+    pub fn push_file(
+        &mut self,
+        path: &str,
+        base: Option<&[u8]>,
+        target: &[u8],
+    ) -> Result<(), serde_json::Error> {
+        if base == Some(target) {
+            return Ok(());
+        }
+        let target_file = serde_json::from_slice::<&RawValue>(target)?;
+        let entries = base.and_then(|base| {
+            Some((
+                serde_json::from_slice::<BTreeMap<String, &RawValue>>(base).ok()?,
+                serde_json::from_slice::<BTreeMap<String, &RawValue>>(target).ok()?,
+            ))
+        });
+        let Some((base_entries, target_entries)) = entries else {
+            self.data.push(PatchOperation::Upsert {
+                path: pointer(path, []),
+                value: target_file.to_owned(),
+            });
+            return Ok(());
+        };
+        for key in base_entries
+            .keys()
+            .filter(|key| !target_entries.contains_key(*key))
+        {
+            self.data.push(PatchOperation::Remove {
+                path: pointer(path, [key.as_str()]),
+            });
+        }
+        for (key, value) in target_entries {
+            if base_entries.get(&key).map(|value| value.get()) != Some(value.get()) {
+                self.data.push(PatchOperation::Upsert {
+                    path: pointer(path, [key.as_str()]),
+                    value: value.to_owned(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Builds a JSON Pointer to the value at the given keys beneath the path of a data file, escaping
+/// any reserved characters in the keys
+fn pointer<'a>(path: &str, keys: impl IntoIterator<Item = &'a str>) -> String {
+    let mut pointer = format!("/{path}");
+    for key in keys {
+        pointer.push('/');
+        pointer.push_str(&key.replace('~', "~0").replace('/', "~1"));
+    }
+    pointer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Patch;
+    use serde_json::{json, Value};
+
+    fn patch(base: Option<Value>, target: Value) -> Value {
+        let mut patch = Patch::default();
+        patch
+            .push_file(
+                "diamond/data/subjects",
+                base.map(|base| serde_json::to_vec(&base).unwrap())
+                    .as_deref(),
+                &serde_json::to_vec(&target).unwrap(),
+            )
+            .unwrap();
+        serde_json::to_value(patch).unwrap()
+    }
+
+    #[test]
+    fn unchanged_file() {
+        let data = json!({"foo": {"sessions": [40]}});
+        assert_eq!(json!({"data": []}), patch(Some(data.clone()), data));
+    }
+
+    #[test]
+    fn changed_entries() {
+        let patch = patch(
+            Some(json!({"foo": {"sessions": [40]}, "bar": {"sessions": [41]}, "qux": {}})),
+            json!({"foo": {"sessions": [40, 42]}, "baz/qux": {"sessions": []}, "qux": {}}),
+        );
+        let expected = json!({"data": [
+            {"op": "remove", "path": "/diamond/data/subjects/bar"},
+            {"op": "upsert", "path": "/diamond/data/subjects/baz~1qux", "value": {"sessions": []}},
+            {"op": "upsert", "path": "/diamond/data/subjects/foo", "value": {"sessions": [40, 42]}},
+        ]});
+        assert_eq!(expected, patch);
+    }
+
+    #[test]
+    fn added_and_replaced_files() {
+        assert_eq!(
+            json!({"data": [{"op": "upsert", "path": "/diamond/data/subjects", "value": {"b": 2}}]}),
+            patch(None, json!({"b": 2}))
+        );
+        assert_eq!(
+            json!({"data": [
+                {"op": "upsert", "path": "/diamond/data/subjects", "value": ["i22", "b21"]}
+            ]}),
+            patch(Some(json!(["i22"])), json!(["i22", "b21"]))
+        );
+        let mut removed = Patch::default();
+        removed.remove_file("diamond/data/old");
+        assert_eq!(
+            json!({"data": [{"op": "remove", "path": "/diamond/data/old"}]}),
+            serde_json::to_value(removed).unwrap()
+        );
+    }
+}
@@ -0,0 +1,537 @@
+use hmac::{Hmac, Mac};
+use schemars::schema::{InstanceType, RootSchema, Schema, SchemaObject, SingleOrVec};
+use serde::{
+    ser::{Error, SerializeMap},
+    Serialize, Serializer,
+};
+use serde_json::Value;
+use sha2::Sha256;
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    str::FromStr,
+};
+
+/// The permissionables whose fields may be filtered
+const FILTERABLE: [&str; 4] = ["subjects", "sessions", "proposals", "beamlines"];
+
+/// The fields which are compared case insensitively, and so are normalized to lowercase before
+/// they are hashed, such that a hashed field always matches its hashed alias
+const CASE_INSENSITIVE: [(&str, &str); 2] = [("subjects", "external_id"), ("subjects", "email")];
+
+/// The fields by which the policy resolves subjects from their aliases, which cannot be hashed
+/// without preventing subjects being identified by them
+const POLICY_ALIASES: [(&str, &str); 1] = [("subjects", "email")];
+
+/// Errors which may occur whilst configuring [`FieldFilters`]
+#[derive(Debug, thiserror::Error)]
+pub enum FieldFilterError {
+    /// The field selector was not of the form `<permissionable>.<field>`
+    #[error("Expected a field of the form <permissionable>.<field>, found {0}")]
+    Malformed(String),
+    /// The permissionable named by the selector does not exist or cannot be filtered
+    #[error("Cannot filter fields of {0}, expected one of {FILTERABLE:?}")]
+    UnknownPermissionable(String),
+    /// The field named by the selector does not exist on the permissionable
+    #[error("{0} has no field {1}")]
+    UnknownField(String, String),
+    /// The field is used by the policy to resolve subjects, so cannot be hashed
+    #[error("{0}.{1} cannot be hashed, as the policy resolves subjects by it")]
+    PolicyAlias(String, String),
+    /// Fields were to be hashed but no key was given with which to hash them
+    #[error("A hash key is required to hash fields")]
+    MissingHashKey,
+}
+
+/// A single field of a permissionable, written as `<permissionable>.<field>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldSelector {
+    /// The name of the permissionable, e.g. `subjects`
+    permissionable: String,
+    /// The name of the field of each entry, e.g. `email`
+    field: String,
+}
+
+impl FromStr for FieldSelector {
+    type Err = FieldFilterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (permissionable, field) = s
+            .split_once('.')
+            .filter(|(permissionable, field)| !permissionable.is_empty() && !field.is_empty())
+            .ok_or_else(|| FieldFilterError::Malformed(s.to_string()))?;
+        if !FILTERABLE.contains(&permissionable) {
+            return Err(FieldFilterError::UnknownPermissionable(
+                permissionable.to_string(),
+            ));
+        }
+        Ok(Self {
+            permissionable: permissionable.to_string(),
+            field: field.to_string(),
+        })
+    }
+}
+
+impl Display for FieldSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.permissionable, self.field)
+    }
+}
+
+/// The treatment of a single field when the bundle is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FieldAction {
+    /// Write the field as is
+    Keep,
+    /// Do not write the field
+    Omit,
+    /// Write the hex encoded HMAC-SHA256 of the field in place of its value
+    Hash,
+}
+
+/// The fields to be allowed, denied or hashed in a single permissionable
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct FieldFilter {
+    /// The only fields to be written, if any are given
+    allow: Option<BTreeSet<String>>,
+    /// The fields to be omitted
+    deny: BTreeSet<String>,
+    /// The fields to be replaced by their digest
+    hash: BTreeSet<String>,
+}
+
+impl FieldFilter {
+    /// Determines how a field should be written
+    fn action(&self, field: &str) -> FieldAction {
+        if self.deny.contains(field)
+            || self
+                .allow
+                .as_ref()
+                .is_some_and(|allow| !allow.contains(field))
+        {
+            FieldAction::Omit
+        } else if self.hash.contains(field) {
+            FieldAction::Hash
+        } else {
+            FieldAction::Keep
+        }
+    }
+}
+
+/// The secret key with which fields are hashed, such that their values cannot be recovered by
+/// hashing guesses without it
+#[derive(Clone, Default, PartialEq, Eq)]
+struct HashKey(Vec<u8>);
+
+impl std::fmt::Debug for HashKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("HashKey").finish_non_exhaustive()
+    }
+}
+
+/// The fields of each permissionable to be omitted or hashed before they are written to a bundle
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FieldFilters {
+    /// The filter of each permissionable, keyed by its name
+    filters: BTreeMap<String, FieldFilter>,
+    /// The key with which hashed fields are hashed, if any
+    hash_key: Option<HashKey>,
+}
+
+impl FieldFilters {
+    /// Creates [`FieldFilters`] from allowed, denied and hashed fields, where a permissionable
+    /// with any allowed fields has all of its other fields omitted
+    pub fn new(
+        allow: Vec<FieldSelector>,
+        deny: Vec<FieldSelector>,
+        hash: Vec<FieldSelector>,
+    ) -> Self {
+        let mut filters = BTreeMap::<String, FieldFilter>::new();
+        for selector in allow {
+            filters
+                .entry(selector.permissionable)
+                .or_default()
+                .allow
+                .get_or_insert_with(BTreeSet::new)
+                .insert(selector.field);
+        }
+        for selector in deny {
+            filters
+                .entry(selector.permissionable)
+                .or_default()
+                .deny
+                .insert(selector.field);
+        }
+        for selector in hash {
+            filters
+                .entry(selector.permissionable)
+                .or_default()
+                .hash
+                .insert(selector.field);
+        }
+        Self {
+            filters,
+            hash_key: None,
+        }
+    }
+
+    /// Sets the secret key with which hashed fields are hashed
+    pub fn with_hash_key(mut self, hash_key: impl Into<Vec<u8>>) -> Self {
+        self.hash_key = Some(HashKey(hash_key.into()));
+        self
+    }
+
+    /// Checks that every filtered field exists in the schema of its permissionable, where the
+    /// schemas are keyed by permissionable name, that no field the policy resolves subjects by is
+    /// hashed, and that a hash key is given if any field is hashed
+    pub fn validate(&self, schemas: &BTreeMap<String, RootSchema>) -> Result<(), FieldFilterError> {
+        for (permissionable, filter) in &self.filters {
+            let fields = schemas
+                .get(permissionable)
+                .and_then(|schema| schema.definitions.get(entry_definition(schema)?))
+                .and_then(|entry| match entry {
+                    Schema::Object(entry) => entry.object.as_ref(),
+                    Schema::Bool(_) => None,
+                })
+                .map(|object| object.properties.keys().cloned().collect::<BTreeSet<_>>())
+                .unwrap_or_default();
+            for field in filter
+                .allow
+                .iter()
+                .flatten()
+                .chain(&filter.deny)
+                .chain(&filter.hash)
+            {
+                if !fields.contains(field) {
+                    return Err(FieldFilterError::UnknownField(
+                        permissionable.clone(),
+                        field.clone(),
+                    ));
+                }
+            }
+            for field in &filter.hash {
+                if POLICY_ALIASES.contains(&(permissionable.as_str(), field.as_str())) {
+                    return Err(FieldFilterError::PolicyAlias(
+                        permissionable.clone(),
+                        field.clone(),
+                    ));
+                }
+            }
+        }
+        let hashes_fields = self.filters.values().any(|filter| {
+            filter
+                .hash
+                .iter()
+                .any(|field| filter.action(field) == FieldAction::Hash)
+        });
+        if hashes_fields && self.hash_key.is_none() {
+            return Err(FieldFilterError::MissingHashKey);
+        }
+        Ok(())
+    }
+
+    /// Wraps the mapping of a permissionable, such that the filters of the permissionable are
+    /// applied to each entry as it is serialized
+    pub fn entries<'a, K, V>(
+        &'a self,
+        permissionable: &'a str,
+        entries: &'a BTreeMap<K, V>,
+    ) -> FilteredEntries<'a, K, V> {
+        FilteredEntries {
+            filters: self,
+            permissionable,
+            entries,
+        }
+    }
+
+    /// Applies the filter of a permissionable to a single serialized entry
+    fn filter_entry(&self, permissionable: &str, filter: &FieldFilter, mut entry: Value) -> Value {
+        if let Value::Object(fields) = &mut entry {
+            fields.retain(|field, _| filter.action(field) != FieldAction::Omit);
+            for (field, value) in fields.iter_mut() {
+                if filter.action(field) == FieldAction::Hash && !value.is_null() {
+                    *value = Value::String(self.hash(permissionable, field, value));
+                }
+            }
+        }
+        entry
+    }
+
+    /// Applies the filters of a permissionable to a single string field, returning [`None`] if
+    /// the field is omitted
+    pub fn filter_field(&self, permissionable: &str, field: &str, value: &str) -> Option<String> {
+        let action = self
+            .filters
+            .get(permissionable)
+            .map_or(FieldAction::Keep, |filter| filter.action(field));
+        match action {
+            FieldAction::Keep => Some(value.to_string()),
+            FieldAction::Omit => None,
+            FieldAction::Hash => {
+                Some(self.hash(permissionable, field, &Value::String(value.to_string())))
+            }
+        }
+    }
+
+    /// Computes the hex encoded HMAC-SHA256 of a field, using the raw contents of strings, in
+    /// lowercase if the field is case insensitive, and the serialized JSON of anything else
+    fn hash(&self, permissionable: &str, field: &str, value: &Value) -> String {
+        let data = match value {
+            Value::String(value) if CASE_INSENSITIVE.contains(&(permissionable, field)) => {
+                Cow::Owned(value.to_lowercase())
+            }
+            Value::String(value) => Cow::Borrowed(value.as_str()),
+            value => Cow::Owned(value.to_string()),
+        };
+        let key = self.hash_key.as_ref().map_or(&[][..], |key| &key.0);
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+        mac.update(data.as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    /// Applies the filters of a permissionable to its schema, removing omitted fields and
+    /// describing hashed fields as strings
+    pub fn filter_schema(&self, permissionable: &str, schema: &mut RootSchema) {
+        let Some(filter) = self.filters.get(permissionable) else {
+            return;
+        };
+        let Some(name) = entry_definition(schema).map(str::to_string) else {
+            return;
+        };
+        let Some(Schema::Object(SchemaObject {
+            object: Some(object),
+            ..
+        })) = schema.definitions.get_mut(&name)
+        else {
+            return;
+        };
+        object
+            .properties
+            .retain(|field, _| filter.action(field) != FieldAction::Omit);
+        object
+            .required
+            .retain(|field| filter.action(field) != FieldAction::Omit);
+        for (field, property) in object.properties.iter_mut() {
+            if filter.action(field) != FieldAction::Hash {
+                continue;
+            }
+            if let Schema::Object(property) = property {
+                let nullable = matches!(
+                    &property.instance_type,
+                    Some(SingleOrVec::Vec(types)) if types.contains(&InstanceType::Null)
+                );
+                property.instance_type = Some(if nullable {
+                    SingleOrVec::Vec(vec![InstanceType::String, InstanceType::Null])
+                } else {
+                    SingleOrVec::Single(Box::new(InstanceType::String))
+                });
+                property.format = None;
+                property.number = None;
+                property.array = None;
+                property.object = None;
+                property.reference = None;
+            }
+        }
+    }
+}
+
+/// The mapping of a permissionable, which is serialized with the [`FieldFilters`] applied to each
+/// entry in turn, such that no more than a single entry is held in memory in its filtered form
+///
+/// Permissionables without a filter are serialized as they are
+pub struct FilteredEntries<'a, K, V> {
+    /// The filters to apply
+    filters: &'a FieldFilters,
+    /// The name of the permissionable, by which its filter is found
+    permissionable: &'a str,
+    /// The entries of the permissionable
+    entries: &'a BTreeMap<K, V>,
+}
+
+impl<K, V> Serialize for FilteredEntries<'_, K, V>
+where
+    K: Serialize,
+    V: Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let Some(filter) = self.filters.filters.get(self.permissionable) else {
+            return self.entries.serialize(serializer);
+        };
+        let mut map = serializer.serialize_map(Some(self.entries.len()))?;
+        for (key, entry) in self.entries {
+            let entry = serde_json::to_value(entry).map_err(S::Error::custom)?;
+            map.serialize_entry(
+                key,
+                &self
+                    .filters
+                    .filter_entry(self.permissionable, filter, entry),
+            )?;
+        }
+        map.end()
+    }
+}
+
+/// Finds the name of the definition of the entries of a permissionable mapping
+fn entry_definition(schema: &RootSchema) -> Option<&str> {
+    let entry = schema
+        .schema
+        .object
+        .as_ref()?
+        .additional_properties
+        .as_deref()?;
+    match entry {
+        Schema::Object(entry) => entry.reference.as_deref()?.strip_prefix("#/definitions/"),
+        Schema::Bool(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FieldFilterError, FieldFilters, FieldSelector};
+    use crate::permissionables::subjects::Subjects;
+    use schemars::schema_for;
+    use serde_json::{json, Value};
+    use std::collections::BTreeMap;
+
+    fn entries(entries: Value) -> BTreeMap<String, Value> {
+        serde_json::from_value(entries).unwrap()
+    }
+
+    fn selectors(selectors: &[&str]) -> Vec<FieldSelector> {
+        selectors
+            .iter()
+            .map(|selector| selector.parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn parse_selector() {
+        let selector = "subjects.email".parse::<FieldSelector>().unwrap();
+        assert_eq!("subjects.email", selector.to_string());
+        assert!(matches!(
+            "email".parse::<FieldSelector>(),
+            Err(FieldFilterError::Malformed(_))
+        ));
+        assert!(matches!(
+            "admin.email".parse::<FieldSelector>(),
+            Err(FieldFilterError::UnknownPermissionable(_))
+        ));
+    }
+
+    #[test]
+    fn filter_entries() {
+        let filters = FieldFilters::new(
+            vec![],
+            selectors(&["subjects.family_name", "subjects.given_name"]),
+            selectors(&["subjects.external_id"]),
+        )
+        .with_hash_key("secret");
+        let entries = entries(json!({
+            "foo": {
+                "permissions": [],
+                "external_id": "ABCDEF0123",
+                "family_name": "Foo",
+                "given_name": "Alice"
+            },
+            "bar": {"permissions": [], "external_id": null}
+        }));
+        let filtered = serde_json::to_value(filters.entries("subjects", &entries)).unwrap();
+        assert_eq!(
+            json!({"permissions": [], "external_id": null}),
+            filtered["bar"]
+        );
+        assert_eq!(
+            vec!["external_id", "permissions"],
+            filtered["foo"]
+                .as_object()
+                .unwrap()
+                .keys()
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            filters
+                .filter_field("subjects", "external_id", "abcdef0123")
+                .as_deref(),
+            filtered["foo"]["external_id"].as_str()
+        );
+        assert_eq!(64, filtered["foo"]["external_id"].as_str().unwrap().len());
+    }
+
+    #[test]
+    fn hash_depends_on_key() {
+        let hash = |key: &str| {
+            FieldFilters::new(vec![], vec![], selectors(&["subjects.family_name"]))
+                .with_hash_key(key)
+                .filter_field("subjects", "family_name", "Foo")
+                .unwrap()
+        };
+        assert_eq!(hash("secret"), hash("secret"));
+        assert_ne!(hash("secret"), hash("other"));
+    }
+
+    #[test]
+    fn filter_allowed_entries() {
+        let filters = FieldFilters::new(selectors(&["sessions.beamline"]), vec![], vec![]);
+        let sessions = entries(json!({"40": {"beamline": "i22", "visit_number": 1}}));
+        assert_eq!(
+            json!({"40": {"beamline": "i22"}}),
+            serde_json::to_value(filters.entries("sessions", &sessions)).unwrap()
+        );
+        let subjects = entries(json!({"foo": {"email": "a"}}));
+        assert_eq!(
+            json!({"foo": {"email": "a"}}),
+            serde_json::to_value(filters.entries("subjects", &subjects)).unwrap()
+        );
+    }
+
+    #[test]
+    fn filter_schema() {
+        let filters = FieldFilters::new(
+            vec![],
+            selectors(&["subjects.family_name", "subjects.permissions"]),
+            selectors(&["subjects.email"]),
+        );
+        let mut schema = schema_for!(Subjects);
+        filters.filter_schema("subjects", &mut schema);
+        let schema = serde_json::to_value(schema).unwrap();
+        let subject = &schema["definitions"]["Subject"];
+        assert!(subject["properties"].get("family_name").is_none());
+        assert!(subject["properties"].get("permissions").is_none());
+        assert!(!subject["required"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("permissions")));
+        assert_eq!(
+            json!(["string", "null"]),
+            subject["properties"]["email"]["type"]
+        );
+    }
+
+    #[test]
+    fn validate_fields() {
+        let schemas = BTreeMap::from([("subjects".to_string(), schema_for!(Subjects))]);
+        FieldFilters::new(vec![], selectors(&["subjects.email"]), vec![])
+            .validate(&schemas)
+            .unwrap();
+        assert!(matches!(
+            FieldFilters::new(vec![], selectors(&["subjects.orcid"]), vec![]).validate(&schemas),
+            Err(FieldFilterError::UnknownField(_, _))
+        ));
+        assert!(matches!(
+            FieldFilters::new(vec![], vec![], selectors(&["subjects.email"]))
+                .with_hash_key("secret")
+                .validate(&schemas),
+            Err(FieldFilterError::PolicyAlias(_, _))
+        ));
+        assert!(matches!(
+            FieldFilters::new(vec![], vec![], selectors(&["subjects.family_name"]))
+                .validate(&schemas),
+            Err(FieldFilterError::MissingHashKey)
+        ));
+        FieldFilters::new(vec![], vec![], selectors(&["subjects.family_name"]))
+            .with_hash_key("secret")
+            .validate(&schemas)
+            .unwrap();
+    }
+}
@@ -0,0 +1,292 @@
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    str::FromStr,
+};
+
+use crate::bundle::{BUNDLE_PREFIX, PERMISSIONABLES};
+
+/// Errors which may occur whilst configuring a [`BundleLayout`]
+#[derive(Debug, thiserror::Error)]
+pub enum LayoutError {
+    /// The data path was not of the form `<permissionable>=<path>`
+    #[error("Expected a data path of the form <permissionable>=<path>, found {0}")]
+    Malformed(String),
+    /// The data path names a permissionable which does not exist
+    #[error("Cannot place {0}, expected one of {PERMISSIONABLES:?}")]
+    UnknownPermissionable(String),
+    /// The path is absolute, has a trailing slash or contains empty segments
+    #[error("Invalid bundle path {0:?}, expected a relative path such as diamond/data")]
+    InvalidPath(String),
+    /// Two permissionables would be placed at the same path, or one beneath the other
+    #[error("The data paths of {0} and {1} overlap")]
+    OverlappingData(String, String),
+    /// Two roots of the bundle would be the same, or one beneath the other
+    #[error("The bundle roots {0} and {1} overlap")]
+    OverlappingRoots(String, String),
+    /// A static data document would be placed at the same path as a permissionable, or one
+    /// beneath the other
+    #[error("The static data {0} overlaps the data path of {1}")]
+    OverlappingStaticData(String, String),
+}
+
+/// The path within the bundle at which a permissionable is placed, written as
+/// `<permissionable>=<path>`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct DataPath {
+    /// The name of the permissionable, e.g. `subjects`
+    permissionable: String,
+    /// The path of the directory containing its `data.json`, e.g. `facility/people`
+    path: String,
+}
+
+impl FromStr for DataPath {
+    type Err = LayoutError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (permissionable, path) = s
+            .split_once('=')
+            .ok_or_else(|| LayoutError::Malformed(s.to_string()))?;
+        if !PERMISSIONABLES.contains(&permissionable) {
+            return Err(LayoutError::UnknownPermissionable(
+                permissionable.to_string(),
+            ));
+        }
+        validate_path(path)?;
+        Ok(Self {
+            permissionable: permissionable.to_string(),
+            path: path.to_string(),
+        })
+    }
+}
+
+impl TryFrom<String> for DataPath {
+    type Error = LayoutError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// The placement of data documents within a bundle, beneath a common prefix unless relocated
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BundleLayout {
+    /// The prefix beneath which data documents are placed by default
+    prefix: String,
+    /// The paths of any permissionables placed elsewhere, keyed by permissionable
+    paths: BTreeMap<String, String>,
+}
+
+impl BundleLayout {
+    /// Creates a [`BundleLayout`] placing data beneath the prefix, except for any relocated
+    /// permissionables
+    pub fn new(prefix: String, data_paths: impl IntoIterator<Item = DataPath>) -> Self {
+        Self {
+            prefix,
+            paths: data_paths
+                .into_iter()
+                .map(|data_path| (data_path.permissionable, data_path.path))
+                .collect(),
+        }
+    }
+
+    /// The path of the directory containing the data document with the given name
+    pub fn path(&self, name: &str) -> String {
+        self.paths
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| format!("{}/{name}", self.prefix))
+    }
+
+    /// The roots of a bundle containing the given permissionables: the prefix and any data paths
+    /// outside of it
+    pub fn roots(&self, permissionables: &BTreeSet<String>) -> Vec<String> {
+        let mut roots = vec![self.prefix.clone()];
+        for (permissionable, path) in &self.paths {
+            if permissionables.contains(permissionable)
+                && !is_beneath(path, &self.prefix)
+                && !roots.contains(path)
+            {
+                roots.push(path.clone());
+            }
+        }
+        roots
+    }
+
+    /// Checks that the prefix is a valid path and that neither the data paths of the given
+    /// permissionables nor the resulting roots overlap
+    pub fn validate(&self, permissionables: &BTreeSet<String>) -> Result<(), LayoutError> {
+        validate_path(&self.prefix)?;
+        let paths = permissionables
+            .iter()
+            .map(|permissionable| (permissionable, self.path(permissionable)))
+            .collect::<Vec<_>>();
+        for (index, (permissionable, path)) in paths.iter().enumerate() {
+            if let Some((other, _)) = paths[index + 1..]
+                .iter()
+                .find(|(_, other_path)| overlaps(path, other_path))
+            {
+                return Err(LayoutError::OverlappingData(
+                    permissionable.to_string(),
+                    other.to_string(),
+                ));
+            }
+        }
+        let roots = self.roots(permissionables);
+        for (index, root) in roots.iter().enumerate() {
+            if let Some(other) = roots[index + 1..]
+                .iter()
+                .find(|other| overlaps(root, other))
+            {
+                return Err(LayoutError::OverlappingRoots(root.clone(), other.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that no static data document, given by name, would be placed at the same path as
+    /// any of the given permissionables, or one beneath the other
+    pub fn validate_static_data<'a>(
+        &self,
+        permissionables: &BTreeSet<String>,
+        static_data: impl IntoIterator<Item = &'a str>,
+    ) -> Result<(), LayoutError> {
+        for name in static_data {
+            let path = self.path(name);
+            if let Some(permissionable) = permissionables
+                .iter()
+                .find(|permissionable| overlaps(&path, &self.path(permissionable)))
+            {
+                return Err(LayoutError::OverlappingStaticData(
+                    name.to_string(),
+                    permissionable.clone(),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for BundleLayout {
+    fn default() -> Self {
+        Self::new(BUNDLE_PREFIX.to_string(), [])
+    }
+}
+
+/// Checks that a path is relative, with no trailing slash or empty segments
+fn validate_path(path: &str) -> Result<(), LayoutError> {
+    if path.split('/').any(str::is_empty) {
+        return Err(LayoutError::InvalidPath(path.to_string()));
+    }
+    Ok(())
+}
+
+/// Whether the path is beneath, but not equal to, the ancestor
+fn is_beneath(path: &str, ancestor: &str) -> bool {
+    path.strip_prefix(ancestor)
+        .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// Whether two paths are equal or one is beneath the other
+fn overlaps(path: &str, other: &str) -> bool {
+    path == other || is_beneath(path, other) || is_beneath(other, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BundleLayout, DataPath, LayoutError};
+    use crate::bundle::PERMISSIONABLES;
+    use std::collections::BTreeSet;
+
+    fn layout(prefix: &str, data_paths: &[&str]) -> BundleLayout {
+        BundleLayout::new(
+            prefix.to_string(),
+            data_paths
+                .iter()
+                .map(|data_path| data_path.parse::<DataPath>().unwrap()),
+        )
+    }
+
+    fn all() -> BTreeSet<String> {
+        PERMISSIONABLES.map(String::from).into()
+    }
+
+    #[test]
+    fn parse_data_path() {
+        assert!(matches!(
+            "subjects".parse::<DataPath>(),
+            Err(LayoutError::Malformed(_))
+        ));
+        assert!(matches!(
+            "admin=diamond/admin".parse::<DataPath>(),
+            Err(LayoutError::UnknownPermissionable(_))
+        ));
+        assert!(matches!(
+            "subjects=/people".parse::<DataPath>(),
+            Err(LayoutError::InvalidPath(_))
+        ));
+    }
+
+    #[test]
+    fn relocated_roots() {
+        let layout = layout(
+            "facility/data",
+            &["subjects=facility/data/people", "aliases=facility/aliases"],
+        );
+        layout.validate(&all()).unwrap();
+        assert_eq!("facility/data/people", layout.path("subjects"));
+        assert_eq!("facility/data/sessions", layout.path("sessions"));
+        assert_eq!(
+            vec!["facility/data", "facility/aliases"],
+            layout.roots(&all())
+        );
+        assert_eq!(
+            vec!["facility/data"],
+            layout.roots(&BTreeSet::from(["subjects".to_string()]))
+        );
+    }
+
+    #[test]
+    fn reject_overlaps() {
+        assert!(matches!(
+            layout("diamond/data", &["subjects=diamond/data/sessions/people"]).validate(&all()),
+            Err(LayoutError::OverlappingData(_, _))
+        ));
+        assert!(matches!(
+            layout("diamond/data", &["aliases=diamond"]).validate(&all()),
+            Err(LayoutError::OverlappingData(_, _))
+        ));
+        assert!(matches!(
+            layout("diamond/data", &["aliases=diamond"])
+                .validate(&BTreeSet::from(["aliases".to_string()])),
+            Err(LayoutError::OverlappingRoots(_, _))
+        ));
+        assert!(matches!(
+            layout("diamond/data/", &[]).validate(&all()),
+            Err(LayoutError::InvalidPath(_))
+        ));
+    }
+
+    #[test]
+    fn reject_static_data_overlaps() {
+        let layout = layout("diamond/data", &["sessions=diamond/data/facility/sessions"]);
+        layout
+            .validate_static_data(&all(), ["admin", "facility/admin", "subjects_extra"])
+            .unwrap();
+        for name in [
+            "subjects",
+            "subjects/extra",
+            "facility",
+            "facility/sessions/extra",
+        ] {
+            assert!(matches!(
+                layout.validate_static_data(&all(), [name]),
+                Err(LayoutError::OverlappingStaticData(_, _))
+            ));
+        }
+        layout
+            .validate_static_data(&BTreeSet::from(["sessions".to_string()]), ["subjects"])
+            .unwrap();
+    }
+}
@@ -6,19 +6,63 @@
 mod built_info;
 /// An Open Policy Agent bundle containing permissionables
 mod bundle;
+/// JSON Patch operations between bundle revisions, for use in delta bundles
+mod delta;
+/// Omission and hashing of permissionable fields before they are written to a bundle
+mod filter;
+/// The placement of data documents within a bundle
+mod layout;
+/// Prometheus metrics describing bundle construction and serving
+mod metrics;
 /// Permissionable relations from the ISPyB database
 mod permissionables;
+/// Named bundles containing a subset of the permissionables
+mod profiles;
+/// Notification of changes to static data files, for reloading bundles
+mod reload;
 /// A [`tower::Service`] which enforces a bearer token requirement
 mod require_bearer;
+/// When each part of the permissionables is due to be refreshed
+mod schedule;
+/// Signing and verification of bundles
+mod signing;
+/// Sources from which permissionables can be fetched
+mod source;
+/// Static data files, in JSON, YAML or TOML, to be included in bundles
+mod static_data;
+/// The outcome of bundle refreshes, for health reporting
+mod status;
+/// Immediate refreshes of the bundles, triggered by request or signal
+mod trigger;
 
-use crate::bundle::{Bundle, NoMetadata};
+use crate::{
+    bundle::{
+        read_tar_gz, Bundle, BundleDataError, BundleOptions, NoMetadata, TarGzWriter, BUNDLE_PREFIX,
+    },
+    filter::{FieldFilterError, FieldFilters, FieldSelector},
+    layout::{BundleLayout, DataPath, LayoutError},
+    metrics::{metrics_endpoint, record_bundle_response, METRICS},
+    permissionables::{Part, Permissionables},
+    profiles::BundleProfiles,
+    reload::{watch_static_data, StaticDataWatcher},
+    schedule::{RefreshInterval, RefreshSchedule},
+    signing::{BundleSigner, SigningAlgorithm},
+    source::{PermissionablesSource, SourceFingerprint},
+    static_data::StaticDataFiles,
+    status::{BundleSummary, RefreshStatus},
+    trigger::{
+        refresh_endpoint, refresh_on_hangup, RefreshTrigger, RefreshTriggers,
+        TRIGGER_COALESCE_PERIOD,
+    },
+};
 use axum::{
     body::Bytes,
     extract::State,
-    http::{HeaderMap, StatusCode},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::map_response,
     response::IntoResponse,
-    routing::get,
-    Router,
+    routing::{get, post},
+    Json, Router,
 };
 use axum_extra::TypedHeader;
 use clap::Parser;
@@ -27,23 +71,23 @@ use glob::{Pattern, PatternError};
 use headers::{ETag, HeaderMapExt, IfNoneMatch};
 use opentelemetry_otlp::WithExportConfig;
 use require_bearer::RequireBearerLayer;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use sqlx::{mysql::MySqlPoolOptions, MySqlPool};
 use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
     fmt::Debug,
     fs::File,
-    hash::Hash,
     io::Write,
     net::{Ipv4Addr, SocketAddr, SocketAddrV4},
-    ops::Add,
+    path::PathBuf,
     str::FromStr,
     sync::Arc,
     time::Duration,
 };
 use tokio::{
     net::TcpListener,
-    sync::RwLock,
-    time::{sleep_until, Instant},
+    sync::{watch, RwLock},
+    time::{sleep, sleep_until, timeout, Instant},
 };
 use tower_http::trace::{
     DefaultMakeSpan, DefaultOnFailure, DefaultOnRequest, DefaultOnResponse, TraceLayer,
@@ -58,27 +102,67 @@ where
     Metadata: Serialize,
 {
     /// The bundle on which the archive is based
-    bundle: Bundle<Metadata>,
+    bundle: Arc<Bundle<Metadata>>,
     /// The serialized bundle as a gzipped tar archive
     file: Bytes,
+    /// The total size of the files in the archive, before compression
+    uncompressed_size: usize,
+    /// Serialized delta bundles as gzipped tar archives, keyed by the revision they apply to
+    deltas: HashMap<String, Bytes>,
 }
 
-impl<Metadata> TryFrom<Bundle<Metadata>> for BundleFile<Metadata>
+impl<Metadata> BundleFile<Metadata>
 where
-    Metadata: Debug + Hash + Serialize,
+    Metadata: Debug + Serialize,
 {
-    type Error = anyhow::Error;
-
-    fn try_from(bundle: Bundle<Metadata>) -> Result<Self, Self::Error> {
+    /// Serializes the [`Bundle`], signing it if a [`BundleSigner`] is provided
+    fn new(bundle: Bundle<Metadata>, signer: Option<&BundleSigner>) -> Result<Self, anyhow::Error> {
+        let mut archive = TarGzWriter::default();
+        bundle.write_tar_gz(&mut archive, signer)?;
         Ok(Self {
-            file: bundle.to_tar_gz()?.into(),
-            bundle,
+            uncompressed_size: archive.uncompressed_size(),
+            file: archive.finish()?.into(),
+            bundle: Arc::new(bundle),
+            deltas: HashMap::new(),
         })
     }
+
+    /// Records the sizes and entry counts of the [`Bundle`] served at the path in the [`METRICS`]
+    fn record_metrics(&self, path: &str) {
+        METRICS.record_bundle(
+            path,
+            self.file.len(),
+            self.uncompressed_size,
+            &self.bundle.entry_counts(),
+        );
+    }
+
+    /// Serializes delta bundles from each of the previous revisions to the current bundle
+    fn with_deltas<'a>(
+        mut self,
+        history: impl IntoIterator<Item = &'a Arc<Bundle<Metadata>>>,
+        signer: Option<&BundleSigner>,
+    ) -> Result<Self, anyhow::Error>
+    where
+        Metadata: 'a,
+    {
+        let bases = history
+            .into_iter()
+            .map(Arc::as_ref)
+            .filter(|base| base.revision() != self.bundle.revision())
+            .collect::<Vec<_>>();
+        let deltas = self.bundle.to_delta_tar_gzs(&bases, signer)?;
+        for (base, delta) in bases.into_iter().zip(deltas) {
+            self.deltas
+                .insert(base.revision().to_string(), delta.into());
+        }
+        Ok(self)
+    }
 }
 
-/// Wrapper to ensure that globs passed via the CLI are valid file globs
-#[derive(Debug, Clone, derive_more::AsRef)]
+/// Wrapper to ensure that globs passed via the CLI or bundle profiles are valid file globs
+#[derive(Debug, Clone, derive_more::AsRef, Deserialize)]
+#[serde(try_from = "String")]
 struct StaticDataGlob(String);
 
 impl FromStr for StaticDataGlob {
@@ -90,9 +174,50 @@ impl FromStr for StaticDataGlob {
     }
 }
 
+impl TryFrom<String> for StaticDataGlob {
+    type Error = PatternError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
 /// A thread safe, mutable, wrapper around the [`BundleFile`]
 type CurrentBundle = Arc<RwLock<BundleFile<NoMetadata>>>;
 
+/// A thread safe, mutable, wrapper around the [`RefreshStatus`]
+type CurrentRefreshStatus = Arc<RwLock<RefreshStatus>>;
+
+/// The state required to serve health, readiness and status requests
+#[derive(Clone)]
+struct StatusState {
+    /// The outcome of recent bundle refreshes
+    refresh_status: CurrentRefreshStatus,
+    /// The age beyond which the bundle being served is considered stale
+    max_bundle_age: Duration,
+}
+
+/// The delay before the first retry of a failed refresh, which doubles on each subsequent failure
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// The delay between noticing a change to static data and rebuilding bundles, such that files
+/// written in several steps have settled
+const STATIC_DATA_SETTLE_DELAY: Duration = Duration::from_millis(500);
+
+/// The media type with which bundles are served to clients which support long polling
+const LONG_POLLING_CONTENT_TYPE: &str = "application/vnd.openpolicyagent.bundles";
+
+/// The state required to serve bundle requests
+#[derive(Clone)]
+struct BundleState {
+    /// The bundle currently being served
+    current_bundle: CurrentBundle,
+    /// A receiver which is notified of the revision whenever a new bundle is served
+    revisions: watch::Receiver<String>,
+    /// The longest duration for which a long polling request will be held
+    max_wait: Duration,
+}
+
 /// Bundler acts as an Open Policy Agent bundle server, providing permissionable data from the
 /// ISPyB database and static data from local files
 #[derive(Debug, Parser)]
@@ -103,6 +228,10 @@ enum Cli {
     Serve(ServeArgs),
     /// Output the bundle schema
     BundleSchema(BundleSchemaArgs),
+    /// Verify the signature of a bundle archive
+    VerifyBundle(VerifyBundleArgs),
+    /// Fetch a single bundle and write it to disk
+    Build(BuildArgs),
 }
 
 /// Arguments to run the service with
@@ -114,21 +243,211 @@ struct ServeArgs {
     /// If enabled, refuse any bundle requests which do not contain this bearer token
     #[arg(long, env = "BUNDLER_REQUIRE_TOKEN")]
     require_token: Option<String>,
-    /// The URL of the ISPyB instance which should be connected to
-    #[arg(long, env = "BUNDLER_DATABASE_URL")]
-    database_url: Url,
+    /// If set, serve POST /refresh to requests containing this bearer token
+    #[arg(long, env = "BUNDLER_ADMIN_TOKEN")]
+    admin_token: Option<String>,
+    /// The source from which permissionables should be fetched
+    #[command(flatten)]
+    source: SourceArgs,
     /// The [`tracing::Level`] to log at
     #[arg(long, env = "BUNDLER_LOG_LEVEL", default_value_t = tracing::Level::INFO)]
     log_level: tracing::Level,
     /// The interval at which ISPyB should be polled
     #[arg(long, env = "BUNDLER_POLLING_INTERVAL", default_value_t=humantime::Duration::from(Duration::from_secs(60)))]
     polling_interval: humantime::Duration,
+    /// The interval at which a permissionable should be polled, if it differs from the polling
+    /// interval, as <permissionable>=<interval>, e.g. subjects=10m
+    #[arg(long, env = "BUNDLER_REFRESH_INTERVALS", value_delimiter = ',')]
+    refresh_interval: Vec<RefreshInterval>,
+    /// The number of consecutive polls which find the source of a permissionable unchanged after which it is fetched regardless, such that a checksum collision cannot hide a change indefinitely
+    #[arg(long, env = "BUNDLER_MAX_UNCHANGED_POLLS", default_value_t = 10)]
+    max_unchanged_polls: u32,
+    /// The number of refresh intervals, of the least frequently refreshed permissionable, after which a bundle which has not been refreshed is considered stale, and the service not ready
+    #[arg(long, env = "BUNDLER_STALE_AFTER_INTERVALS", default_value_t = 3)]
+    stale_after_intervals: u32,
     /// The URL of the OpenTelemetry collector to send traces to
     #[arg(long, env = "BUNDLER_OTEL_COLLECTOR_URL")]
     otel_collector_url: Option<Url>,
-    /// Paths to any static data files that should be included in the bundle - can be globs
+    /// Static data files that should be included in the bundle
+    #[command(flatten)]
+    static_data: StaticDataArgs,
+    /// Options for placing data within the bundle
+    #[command(flatten)]
+    layout: LayoutArgs,
+    /// The number of previous bundle revisions from which delta bundles should be served
+    #[arg(long, env = "BUNDLER_DELTA_HISTORY", default_value_t = 0)]
+    delta_history: usize,
+    /// The longest duration for which a long polling bundle request will be held open
+    #[arg(long, env = "BUNDLER_MAX_LONG_POLLING_WAIT", default_value_t=humantime::Duration::from(Duration::from_secs(300)))]
+    max_long_polling_wait: humantime::Duration,
+    /// Options for signing the bundle
+    #[command(flatten)]
+    signing: SigningArgs,
+    /// Options for filtering permissionable fields
+    #[command(flatten)]
+    field_filters: FieldFilterArgs,
+    /// The path to a JSON file of named bundle profiles, each served at /bundles/{name}.tar.gz
+    #[arg(long, env = "BUNDLER_PROFILES", value_parser = clap::value_parser!(ClioPath).exists().is_file())]
+    profiles: Option<ClioPath>,
+}
+
+/// Arguments to select the source of permissionables with
+#[derive(Debug, Parser)]
+#[group(required = true, multiple = true)]
+struct SourceArgs {
+    /// The URL of the ISPyB instance which should be connected to
+    #[arg(long, env = "BUNDLER_DATABASE_URL")]
+    database_url: Option<Url>,
+    /// The path to a JSON snapshot of permissionables, to be used in place of ISPyB
+    #[arg(long, env = "BUNDLER_SNAPSHOT", value_parser = clap::value_parser!(ClioPath).exists().is_file(), conflicts_with_all = ["database_url", "sql_dump"])]
+    snapshot: Option<ClioPath>,
+    /// The path to a SQL dump, which creates and populates the ISPyB tables, to load into the database before fetching from it in place of ISPyB - a MySQL server is still required, and the dump is only loaded into a database which contains no tables, so use a snapshot to develop without MySQL
+    #[arg(long, env = "BUNDLER_SQL_DUMP", value_parser = clap::value_parser!(ClioPath).exists().is_file(), requires = "database_url")]
+    sql_dump: Option<ClioPath>,
+}
+
+impl SourceArgs {
+    /// Connects to the selected [`PermissionablesSource`]
+    async fn connect(self) -> Result<PermissionablesSource, BundleDataError> {
+        match (self.database_url, self.snapshot) {
+            (Some(database_url), _) => {
+                let ispyb_pool = connect_ispyb(database_url).await?;
+                match self.sql_dump {
+                    Some(sql_dump) => {
+                        PermissionablesSource::from_sql_dump(ispyb_pool, sql_dump.path()).await
+                    }
+                    None => Ok(PermissionablesSource::Ispyb(ispyb_pool)),
+                }
+            }
+            (None, Some(snapshot)) => Ok(PermissionablesSource::Snapshot(
+                snapshot.path().to_path_buf(),
+            )),
+            (None, None) => unreachable!("Source group is required by CLI"),
+        }
+    }
+}
+
+/// Arguments to sign bundles with
+#[derive(Debug, Parser)]
+struct SigningArgs {
+    /// The path to a key with which bundles should be signed - a shared secret for HS256 or a PEM encoded private key otherwise
+    #[arg(long, env = "BUNDLER_SIGNING_KEY")]
+    signing_key: Option<ClioPath>,
+    /// The algorithm with which bundles should be signed
+    #[arg(long, env = "BUNDLER_SIGNING_ALGORITHM", value_enum, default_value_t = SigningAlgorithm::RS256)]
+    signing_algorithm: SigningAlgorithm,
+    /// The identifier of the signing key, as configured in Open Policy Agent
+    #[arg(long, env = "BUNDLER_SIGNING_KEY_ID")]
+    signing_key_id: Option<String>,
+}
+
+impl SigningArgs {
+    /// Reads the signing key, if one was provided
+    fn signer(self) -> Result<Option<BundleSigner>, signing::SigningError> {
+        self.signing_key
+            .map(|key| {
+                BundleSigner::from_file(self.signing_algorithm, key.path(), self.signing_key_id)
+            })
+            .transpose()
+    }
+}
+
+/// Arguments to omit or hash permissionable fields with
+#[derive(Debug, Parser)]
+struct FieldFilterArgs {
+    /// Fields which should be included in the bundle, as <permissionable>.<field> - all other fields of the permissionable are omitted
+    #[arg(long, env = "BUNDLER_ALLOW_FIELDS", value_delimiter = ',')]
+    allow_field: Vec<FieldSelector>,
+    /// Fields which should be omitted from the bundle, as <permissionable>.<field>
+    #[arg(long, env = "BUNDLER_DENY_FIELDS", value_delimiter = ',')]
+    deny_field: Vec<FieldSelector>,
+    /// Fields which should be replaced by their HMAC-SHA256 in the bundle, as <permissionable>.<field>
+    #[arg(long, env = "BUNDLER_HASH_FIELDS", value_delimiter = ',')]
+    hash_field: Vec<FieldSelector>,
+    /// The secret key with which hashed fields are hashed
+    #[arg(long, env = "BUNDLER_HASH_KEY")]
+    hash_key: Option<String>,
+}
+
+impl FieldFilterArgs {
+    /// Creates the [`FieldFilters`], checking that each field exists on its permissionable
+    fn filters(self) -> Result<FieldFilters, FieldFilterError> {
+        let mut field_filters =
+            FieldFilters::new(self.allow_field, self.deny_field, self.hash_field);
+        if let Some(hash_key) = self.hash_key {
+            field_filters = field_filters.with_hash_key(hash_key);
+        }
+        field_filters.validate(&Bundle::<NoMetadata>::permissionable_schemas())?;
+        Ok(field_filters)
+    }
+}
+
+/// Arguments to select static data files with
+#[derive(Debug, Parser)]
+struct StaticDataArgs {
+    /// Paths to any static data files that should be included in the bundle, in JSON, YAML or TOML, each checked against any sibling *.schema.json - can be globs
     #[arg(long, env = "BUNDLER_STATIC_DATA")]
     static_data: Vec<StaticDataGlob>,
+    /// The directory relative to which static data files are placed in the bundle, such that subdirectories map to nested data paths - files are placed by their stem alone if unset
+    #[arg(long, env = "BUNDLER_STATIC_DATA_BASE")]
+    static_data_base: Option<PathBuf>,
+}
+
+impl StaticDataArgs {
+    /// Creates the [`StaticDataFiles`] matched by the globs
+    fn files(self) -> StaticDataFiles {
+        StaticDataFiles::new(self.static_data, self.static_data_base)
+    }
+}
+
+/// Arguments to place data within the bundle with
+#[derive(Debug, Parser)]
+struct LayoutArgs {
+    /// The prefix beneath which data is placed in the bundle, which is also the root of the bundle
+    #[arg(long, env = "BUNDLER_ROOTS_PREFIX", default_value = BUNDLE_PREFIX)]
+    roots_prefix: String,
+    /// Paths at which permissionables should be placed instead of beneath the prefix, as <permissionable>=<path> - any outside the prefix become additional roots
+    #[arg(long, env = "BUNDLER_DATA_PATHS", value_delimiter = ',')]
+    data_path: Vec<DataPath>,
+}
+
+impl LayoutArgs {
+    /// Creates [`BundleOptions`] including every permissionable, checking that neither their data
+    /// paths nor the bundle roots overlap
+    fn options(self, field_filters: FieldFilters) -> Result<BundleOptions, LayoutError> {
+        let options = BundleOptions::new(
+            BundleLayout::new(self.roots_prefix, self.data_path),
+            field_filters,
+        );
+        options.validate()?;
+        Ok(options)
+    }
+}
+
+/// Arguments to build a bundle with
+#[derive(Debug, Parser)]
+struct BuildArgs {
+    /// The source from which permissionables should be fetched
+    #[command(flatten)]
+    source: SourceArgs,
+    /// Static data files that should be included in the bundle
+    #[command(flatten)]
+    static_data: StaticDataArgs,
+    /// Options for placing data within the bundle
+    #[command(flatten)]
+    layout: LayoutArgs,
+    /// The path to write the bundle to
+    #[arg(short, long)]
+    output: ClioPath,
+    /// Write the bundle as an unpacked directory, rather than a gzipped tar archive
+    #[arg(long)]
+    unpacked: bool,
+    /// Options for signing the bundle
+    #[command(flatten)]
+    signing: SigningArgs,
+    /// Options for filtering permissionable fields
+    #[command(flatten)]
+    field_filters: FieldFilterArgs,
 }
 
 /// Arguments to output the schema with
@@ -137,6 +456,26 @@ struct BundleSchemaArgs {
     /// The path to write the schema to
     #[arg(short, long, value_parser = clap::value_parser!(ClioPath).exists().is_dir())]
     path: Option<ClioPath>,
+    /// Static data files whose JSON Schemas, in sibling *.schema.json files, should also be output
+    #[command(flatten)]
+    static_data: StaticDataArgs,
+    /// Options for filtering permissionable fields
+    #[command(flatten)]
+    field_filters: FieldFilterArgs,
+}
+
+/// Arguments to verify a bundle signature with
+#[derive(Debug, Parser)]
+struct VerifyBundleArgs {
+    /// The path of the gzipped tar bundle archive
+    #[arg(value_parser = clap::value_parser!(ClioPath).exists().is_file())]
+    bundle: ClioPath,
+    /// The path to a key with which to verify the signature - a shared secret for HS256 or a PEM encoded public key otherwise
+    #[arg(long)]
+    key: ClioPath,
+    /// The algorithm with which the bundle was signed
+    #[arg(long, value_enum, default_value_t = SigningAlgorithm::RS256)]
+    algorithm: SigningAlgorithm,
 }
 
 #[tokio::main]
@@ -147,6 +486,8 @@ async fn main() {
     match args {
         Cli::Serve(args) => serve(args).await,
         Cli::BundleSchema(args) => bundle_schema(args),
+        Cli::VerifyBundle(args) => verify_bundle(args),
+        Cli::Build(args) => build(args).await,
     }
 }
 
@@ -154,15 +495,48 @@ async fn main() {
 async fn serve(args: ServeArgs) {
     setup_telemetry(args.log_level, args.otel_collector_url).unwrap();
 
-    let ispyb_pool = connect_ispyb(args.database_url).await.unwrap();
-    let current_bundle = fetch_initial_bundle(&args.static_data, &ispyb_pool)
-        .await
-        .unwrap();
-    let app = Router::new()
-        .route("/bundle.tar.gz", get(bundle_endpoint))
-        .with_state(current_bundle.clone())
-        .route_layer(RequireBearerLayer::new(args.require_token))
+    let signer = args.signing.signer().unwrap().map(Arc::new);
+    let field_filters = args.field_filters.filters().unwrap();
+    let profiles = args
+        .profiles
+        .map(|profiles| BundleProfiles::read(profiles.path()))
+        .transpose()
+        .unwrap()
+        .unwrap_or_default();
+    let source = args.source.connect().await.unwrap();
+    let options = args.layout.options(field_filters.clone()).unwrap();
+    let fingerprint = source.fingerprint().await.ok();
+    let (bundles, permissionables, refresh_status) = fetch_initial_bundles(
+        options,
+        args.static_data.files(),
+        args.require_token,
+        &profiles,
+        &source,
+        &field_filters,
+        signer.as_deref(),
+    )
+    .await
+    .unwrap();
+    let refresh_status = Arc::new(RwLock::new(refresh_status));
+    let schedule = RefreshSchedule::new(*args.polling_interval, &args.refresh_interval);
+    let max_wait = args.max_long_polling_wait.into();
+    let (refresh_trigger, refresh_triggers) = RefreshTrigger::new();
+    let app = bundles
+        .iter()
+        .fold(Router::new(), |app, bundle| {
+            app.merge(bundle.router(max_wait))
+        })
+        .merge(admin_router(args.admin_token, refresh_trigger.clone()))
         .route("/healthz", get(health_endpoint))
+        .route("/readyz", get(ready_endpoint))
+        .route("/status", get(status_endpoint))
+        .route("/metrics", get(metrics_endpoint))
+        .with_state(StatusState {
+            refresh_status: refresh_status.clone(),
+            max_bundle_age: schedule
+                .longest_interval()
+                .saturating_mul(args.stale_after_intervals),
+        })
         .fallback(fallback_endpoint)
         .layer(
             TraceLayer::new_for_http()
@@ -172,14 +546,43 @@ async fn serve(args: ServeArgs) {
                 .on_failure(DefaultOnFailure::new().level(tracing::Level::INFO)),
         );
 
+    let (static_data_watcher, static_data_changed) =
+        match watch_static_data(bundles.iter().map(|bundle| &bundle.static_data)) {
+            Ok((watcher, changed)) => (Some(watcher), changed),
+            Err(err) => {
+                tracing::warn!("Static data will only be reloaded when polling: {err}");
+                (None, watch::channel(()).1)
+            }
+        };
+
     let mut tasks = tokio::task::JoinSet::new();
-    tasks.spawn(update_bundle(
-        current_bundle,
-        args.static_data,
-        ispyb_pool,
-        args.polling_interval.into(),
-    ));
+    tasks.spawn(
+        BundleUpdater {
+            bundles,
+            permissionables,
+            fingerprints: fingerprint
+                .map(|fingerprint| {
+                    Part::ALL
+                        .into_iter()
+                        .map(|part| (part, fingerprint.of_part(part)))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            unchanged_polls: BTreeMap::new(),
+            max_unchanged_polls: args.max_unchanged_polls,
+            static_data_changed,
+            static_data_watcher,
+            refresh_triggers,
+            refresh_status,
+            source,
+            delta_history: args.delta_history,
+            signer,
+        }
+        .run(schedule),
+    );
     tasks.spawn(serve_endpoints(args.port, app));
+    // Detached from the tasks, as bundles continue to be served should it stop
+    tokio::spawn(refresh_on_hangup(refresh_trigger));
     tasks.join_next().await.unwrap().unwrap()
 }
 
@@ -253,24 +656,56 @@ async fn connect_ispyb(database_url: Url) -> Result<MySqlPool, sqlx::Error> {
     connection
 }
 
-/// Fetches the initial [`Bundle`] from ISPyB and any static files, and produces the corresponding
-/// [`BundleFile`]
+/// Fetches the initial [`Permissionables`] from the source and assembles the default bundle,
+/// followed by that of each profile, reporting the status of every bundle and returning the
+/// permissionables for reuse when static data changes
 #[instrument]
-async fn fetch_initial_bundle(
-    static_data: &[StaticDataGlob],
-    ispyb_pool: &MySqlPool,
-) -> Result<Arc<RwLock<BundleFile<NoMetadata>>>, anyhow::Error> {
+async fn fetch_initial_bundles(
+    options: BundleOptions,
+    static_data: StaticDataFiles,
+    require_token: Option<String>,
+    profiles: &BundleProfiles,
+    source: &PermissionablesSource,
+    field_filters: &FieldFilters,
+    signer: Option<&BundleSigner>,
+) -> Result<(Vec<ServedBundle>, Arc<Permissionables>, RefreshStatus), anyhow::Error> {
     tracing::info!("Fetching initial bundle");
-    let bundle = Arc::new(RwLock::new(BundleFile::try_from(
-        Bundle::fetch(NoMetadata, static_data, ispyb_pool)
-            .await
-            .unwrap(),
-    )?));
-    tracing::info!(
-        "Using bundle with revison: {}",
-        bundle.as_ref().read().await.bundle.revision()
-    );
-    Ok(bundle)
+    let fetch_start = Instant::now();
+    let permissionables = Arc::new(source.fetch().await?);
+    let fetch_duration = fetch_start.elapsed();
+    let mut bundles = vec![
+        ServedBundle::new(
+            "/bundle.tar.gz".to_string(),
+            options,
+            static_data,
+            require_token.clone(),
+            &permissionables,
+            signer,
+        )
+        .await?,
+    ];
+    for (name, profile) in profiles.iter() {
+        bundles.push(
+            ServedBundle::new(
+                format!("/bundles/{name}.tar.gz"),
+                profile.options(field_filters),
+                profile.static_data(),
+                profile
+                    .require_token()
+                    .map(str::to_string)
+                    .or_else(|| require_token.clone()),
+                &permissionables,
+                signer,
+            )
+            .await?,
+        );
+    }
+    let mut bundle_summaries = BTreeMap::new();
+    for bundle in &bundles {
+        bundle_summaries.insert(bundle.path.clone(), bundle.summarise().await);
+    }
+    let refresh_status = RefreshStatus::new(bundle_summaries, fetch_duration);
+    Ok((bundles, permissionables, refresh_status))
 }
 
 /// Bind to the provided socket address and serve the application endpoints
@@ -281,76 +716,495 @@ async fn serve_endpoints(port: u16, app: Router) {
     axum::serve(listener, app).await.unwrap()
 }
 
-/// Periodically update the bundle with new data from ISPyB and any static files matching the given
-/// glob patterns.
-async fn update_bundle(
-    current_bundle: impl AsRef<RwLock<BundleFile<NoMetadata>>>,
-    static_data: Vec<StaticDataGlob>,
-    ispyb_pool: MySqlPool,
-    polling_interval: Duration,
-) {
-    let mut next_fetch = Instant::now().add(polling_interval);
-
-    loop {
-        sleep_until(next_fetch).await;
-        next_fetch = next_fetch.add(polling_interval);
-        tracing::info!("Updating bundle");
-        let bundle = Bundle::fetch(NoMetadata, &static_data, &ispyb_pool)
+/// Routes requests to trigger an immediate refresh, if an admin token is set, refusing those
+/// without it
+fn admin_router<S>(admin_token: Option<String>, refresh_trigger: RefreshTrigger) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    match admin_token {
+        Some(admin_token) => Router::new()
+            .route("/refresh", post(refresh_endpoint))
+            .with_state(refresh_trigger)
+            .route_layer(RequireBearerLayer::new(Some(admin_token))),
+        None => Router::new(),
+    }
+}
+
+/// A bundle served at a single path, with its own revisions and delta history
+struct ServedBundle {
+    /// The path at which the bundle is served
+    path: String,
+    /// The permissionables to include, the prefix to place them beneath and the filters to apply
+    options: BundleOptions,
+    /// Any static data files that should be included in the bundle
+    static_data: StaticDataFiles,
+    /// If set, the bearer token required to fetch the bundle
+    require_token: Option<String>,
+    /// The bundle currently being served
+    current_bundle: CurrentBundle,
+    /// A sender which notifies long polling requests of new revisions
+    revision_sender: watch::Sender<String>,
+    /// Previous bundles, most recent first, from which delta bundles are produced
+    history: VecDeque<Arc<Bundle<NoMetadata>>>,
+}
+
+impl ServedBundle {
+    /// Assembles the initial bundle from the [`Permissionables`] and any static files
+    async fn new(
+        path: String,
+        options: BundleOptions,
+        static_data: StaticDataFiles,
+        require_token: Option<String>,
+        permissionables: &Arc<Permissionables>,
+        signer: Option<&BundleSigner>,
+    ) -> Result<Self, anyhow::Error> {
+        let bundle = Bundle::new(
+            NoMetadata,
+            permissionables.clone(),
+            static_data.read().await?,
+            options.clone(),
+        )?;
+        let bundle_file = BundleFile::new(bundle, signer)?;
+        tracing::info!(
+            "Serving bundle with revision {} at {path}",
+            bundle_file.bundle.revision()
+        );
+        let (revision_sender, _) = watch::channel(bundle_file.bundle.revision().to_string());
+        Ok(Self {
+            path,
+            options,
+            static_data,
+            require_token,
+            current_bundle: Arc::new(RwLock::new(bundle_file)),
+            revision_sender,
+            history: VecDeque::new(),
+        })
+    }
+
+    /// Routes requests for the bundle, refusing those without the required bearer token
+    fn router<S>(&self, max_wait: Duration) -> Router<S>
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        Router::new()
+            .route(&self.path, get(bundle_endpoint))
+            .route_layer(map_response(record_bundle_response))
+            .with_state(BundleState {
+                current_bundle: self.current_bundle.clone(),
+                revisions: self.revision_sender.subscribe(),
+                max_wait,
+            })
+            .route_layer(RequireBearerLayer::new(self.require_token.clone()))
+    }
+
+    /// Summarises the bundle currently being served, recording its sizes and entry counts in the
+    /// [`METRICS`]
+    async fn summarise(&self) -> BundleSummary {
+        let current_bundle = self.current_bundle.as_ref().read().await;
+        current_bundle.record_metrics(&self.path);
+        BundleSummary::new(&current_bundle.bundle)
+    }
+
+    /// Reassembles the bundle from freshly fetched [`Permissionables`] and swaps it in place of
+    /// the one currently being served
+    async fn update(
+        &mut self,
+        permissionables: &Arc<Permissionables>,
+        delta_history: usize,
+        signer: Option<&BundleSigner>,
+    ) -> Result<(), anyhow::Error> {
+        let bundle = Bundle::new(
+            NoMetadata,
+            permissionables.clone(),
+            self.static_data.read().await?,
+            self.options.clone(),
+        )?;
+        let old_bundle = self.current_bundle.as_ref().read().await.bundle.clone();
+        let mut history = self.history.clone();
+        if delta_history > 0 && old_bundle.revision() != bundle.revision() {
+            history.truncate(delta_history - 1);
+            history.push_front(old_bundle.clone());
+        }
+        let bundle_file = BundleFile::new(bundle, signer)?.with_deltas(&history, signer)?;
+        self.history = history;
+        let old_revision = old_bundle.revision();
+        let new_revision = bundle_file.bundle.revision().to_string();
+        *self.current_bundle.as_ref().write().await = bundle_file;
+        tracing::info!(
+            "Updated bundle at {} from {} to {}",
+            self.path,
+            old_revision,
+            new_revision
+        );
+        self.revision_sender.send_if_modified(|revision| {
+            let modified = *revision != new_revision;
+            *revision = new_revision;
+            modified
+        });
+        Ok(())
+    }
+}
+
+/// The state required to periodically update the bundles with new data from the permissionables
+/// source and any static files matching their glob patterns
+struct BundleUpdater {
+    /// The bundles being served, the first of which is the default bundle
+    bundles: Vec<ServedBundle>,
+    /// The permissionables fetched by the most recent successful refresh
+    permissionables: Arc<Permissionables>,
+    /// The fingerprint of that which each part of the permissionables was fetched from, when it
+    /// was fetched, if known
+    fingerprints: BTreeMap<Part, SourceFingerprint>,
+    /// The number of consecutive polls for which each part has not been fetched as the
+    /// fingerprint of its source was unchanged
+    unchanged_polls: BTreeMap<Part, u32>,
+    /// The number of consecutive unchanged polls after which a part is fetched regardless
+    max_unchanged_polls: u32,
+    /// Marked as changed whenever any static data may have changed
+    static_data_changed: watch::Receiver<()>,
+    /// The watcher of static data files, which stops watching when dropped
+    static_data_watcher: Option<StaticDataWatcher>,
+    /// Triggers for immediate refreshes, by request or signal
+    refresh_triggers: RefreshTriggers,
+    /// The outcome of recent refreshes
+    refresh_status: CurrentRefreshStatus,
+    /// The source from which permissionables are fetched
+    source: PermissionablesSource,
+    /// The number of previous revisions from which delta bundles should be produced
+    delta_history: usize,
+    /// The key with which bundles are signed, if any
+    signer: Option<Arc<BundleSigner>>,
+}
+
+impl BundleUpdater {
+    /// Refreshes each part of the permissionables as it falls due, retrying with exponential
+    /// backoff on failure, and rebuilds the bundles from the cached permissionables whenever
+    /// static data changes
+    ///
+    /// Triggered refreshes of every part are performed immediately, after which polling resumes
+    /// as though they were scheduled. The previous bundles continue to be served whilst refreshes
+    /// are failing
+    async fn run(mut self, mut schedule: RefreshSchedule) {
+        let mut static_data_changed = self.static_data_changed.clone();
+
+        loop {
+            tokio::select! {
+                () = sleep_until(schedule.next_refresh()) => {
+                    let parts = schedule.due(Instant::now());
+                    tracing::info!("Updating bundles with fresh {}", part_names(&parts));
+                    match self
+                        .refresh_and_record(&parts, schedule.shortest_interval(&parts), false)
+                        .await
+                    {
+                        Ok(_) => schedule.refreshed(&parts),
+                        Err((_, retry_delay)) => schedule.retry(&parts, retry_delay),
+                    }
+                }
+                Some(mut pending) = self.refresh_triggers.recv() => {
+                    sleep(TRIGGER_COALESCE_PERIOD).await;
+                    self.refresh_triggers.gather(&mut pending);
+                    tracing::info!("Updating bundles on demand ({} triggers)", pending.count());
+                    let parts = BTreeSet::from(Part::ALL);
+                    match self
+                        .refresh_and_record(&parts, schedule.shortest_interval(&parts), true)
+                        .await
+                    {
+                        Ok(revision) => {
+                            pending.respond(Ok(&revision));
+                            schedule.refreshed_all();
+                        }
+                        Err((err, retry_delay)) => {
+                            pending.respond(Err(&err));
+                            schedule.retry(&parts, retry_delay);
+                        }
+                    }
+                }
+                Ok(()) = static_data_changed.changed() => {
+                    sleep(STATIC_DATA_SETTLE_DELAY).await;
+                    static_data_changed.mark_unchanged();
+                    tracing::info!("Static data changed, rebuilding bundles");
+                    self.update_bundles().await;
+                }
+            }
+        }
+    }
+
+    /// Refreshes the parts and records the outcome, returning the revision of the default bundle,
+    /// or the error and the delay, up to the maximum, before the refresh should be retried
+    async fn refresh_and_record(
+        &mut self,
+        parts: &BTreeSet<Part>,
+        max_retry_delay: Duration,
+        force: bool,
+    ) -> Result<String, (anyhow::Error, Duration)> {
+        match self.refresh(parts, force).await {
+            Ok(revision) => {
+                self.refresh_status.write().await.succeeded();
+                Ok(revision)
+            }
+            Err(err) => {
+                let failures = self.refresh_status.write().await.failed(&err);
+                let retry_delay = retry_delay(failures, max_retry_delay);
+                tracing::error!(
+                    "Failed to update bundles ({failures} consecutive failures), retrying in {retry_delay:?}: {err}"
+                );
+                Err((err, retry_delay))
+            }
+        }
+    }
+
+    /// Fetches the parts of the permissionables, then reassembles each bundle from them and the
+    /// cached remainder, returning the revision of the default bundle
+    ///
+    /// Unless forced, parts are not fetched if the fingerprint of the source is unchanged since
+    /// they were last fetched, up to the maximum number of consecutive unchanged polls. The bundles are then only reassembled if any part was fetched, if
+    /// changes to some static data may not be noticed by the watcher, or if the previous attempt
+    /// to update any of them failed. Failures to update individual bundles are recorded against
+    /// those bundles, rather than failing the refresh
+    async fn refresh(
+        &mut self,
+        parts: &BTreeSet<Part>,
+        force: bool,
+    ) -> Result<String, anyhow::Error> {
+        let fetch_start = Instant::now();
+        let fingerprint = match self.source.fingerprint().await {
+            Ok(fingerprint) => Some(fingerprint),
+            Err(err) => {
+                tracing::warn!("Unable to detect changes, fetching permissionables: {err}");
+                None
+            }
+        };
+        let changed = parts
+            .iter()
+            .copied()
+            .filter(|part| {
+                force
+                    || fingerprint.as_ref().is_none_or(|fingerprint| {
+                        self.fingerprints.get(part) != Some(&fingerprint.of_part(*part))
+                    })
+                    || self.unchanged_polls.get(part).copied().unwrap_or_default()
+                        >= self.max_unchanged_polls
+            })
+            .collect::<BTreeSet<_>>();
+        for part in parts {
+            if changed.contains(part) {
+                self.unchanged_polls.remove(part);
+            } else {
+                *self.unchanged_polls.entry(*part).or_default() += 1;
+            }
+        }
+        if changed.is_empty() {
+            tracing::info!("Permissionables unchanged, skipping fetch");
+        } else {
+            let mut permissionables = Permissionables::clone(&self.permissionables);
+            self.source
+                .fetch_parts(&changed, &mut permissionables)
+                .await?;
+            self.permissionables = Arc::new(permissionables);
+            for part in &changed {
+                match &fingerprint {
+                    Some(fingerprint) => {
+                        self.fingerprints.insert(*part, fingerprint.of_part(*part))
+                    }
+                    None => self.fingerprints.remove(part),
+                };
+            }
+        }
+        self.refresh_status
+            .write()
             .await
-            .unwrap();
-        let bundle_file = BundleFile::try_from(bundle).unwrap();
-        let old_revision = current_bundle
+            .fetched(fetch_start.elapsed());
+        if !changed.is_empty()
+            || !self
+                .static_data_watcher
+                .as_ref()
+                .is_some_and(StaticDataWatcher::is_complete)
+            || self.refresh_status.read().await.any_bundle_failed()
+        {
+            self.update_bundles().await;
+        } else {
+            self.refresh_status.write().await.bundles_unchanged();
+        }
+        Ok(self.bundles[0]
+            .current_bundle
             .as_ref()
             .read()
             .await
             .bundle
             .revision()
-            .to_owned();
-        *current_bundle.as_ref().write().await = bundle_file;
-        tracing::info!(
-            "Updated bundle from {} to {}",
-            old_revision,
-            current_bundle.as_ref().read().await.bundle.revision()
-        );
+            .to_string())
+    }
+
+    /// Reassembles each bundle from the cached permissionables and freshly read static data,
+    /// recording the outcome for each bundle separately, such that a failure to update one does
+    /// not prevent the others from being updated
+    ///
+    /// Bundles which fail to update continue to be served and are retried on the next refresh
+    async fn update_bundles(&mut self) {
+        for bundle in &mut self.bundles {
+            match bundle
+                .update(
+                    &self.permissionables,
+                    self.delta_history,
+                    self.signer.as_deref(),
+                )
+                .await
+            {
+                Ok(()) => {
+                    let summary = bundle.summarise().await;
+                    self.refresh_status
+                        .write()
+                        .await
+                        .bundle_updated(&bundle.path, summary);
+                }
+                Err(err) => {
+                    tracing::error!("Failed to update bundle at {}: {err}", bundle.path);
+                    self.refresh_status
+                        .write()
+                        .await
+                        .bundle_failed(&bundle.path, &err);
+                }
+            }
+        }
     }
 }
 
+/// The names of the parts of the permissionables, for logging
+fn part_names(parts: &BTreeSet<Part>) -> String {
+    parts
+        .iter()
+        .map(|part| part.name())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The delay before retrying a refresh after a number of consecutive failures, which doubles with
+/// each failure up to the maximum delay
+fn retry_delay(failures: u32, max_delay: Duration) -> Duration {
+    let multiplier = 1_u32
+        .checked_shl(failures.saturating_sub(1))
+        .unwrap_or(u32::MAX);
+    INITIAL_RETRY_DELAY
+        .saturating_mul(multiplier)
+        .min(max_delay)
+}
+
 /// Returns the Open Policy Agent bundle in gzipped tar format
 ///
 /// ETag matching is supported via the 'If-None-Match' header, requests containing this header will not recieve any data if it matches the current bundle version
+/// and will recieve a delta bundle if it matches a previous bundle version which is still held
+///
+/// Long polling is supported via the 'Prefer: wait=N' header, requests containing this header and a matching ETag will be held until a new bundle is available or N
+/// seconds have elapsed
 async fn bundle_endpoint(
-    State(current_bundle): State<CurrentBundle>,
+    State(BundleState {
+        current_bundle,
+        mut revisions,
+        max_wait,
+    }): State<BundleState>,
+    request_headers: HeaderMap,
     if_none_match: Option<TypedHeader<IfNoneMatch>>,
 ) -> impl IntoResponse {
-    let etag = ETag::from_str(&format!(
-        r#""{}""#,
-        current_bundle.as_ref().read().await.bundle.revision()
-    ))
-    .unwrap();
+    let wait = prefer_wait(&request_headers, max_wait);
+    revisions.borrow_and_update();
     let mut headers = HeaderMap::new();
+    if wait.is_some() {
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static(LONG_POLLING_CONTENT_TYPE),
+        );
+    }
+    if let (Some(wait), Some(TypedHeader(if_none_match))) = (wait, &if_none_match) {
+        let revision = current_bundle
+            .as_ref()
+            .read()
+            .await
+            .bundle
+            .revision()
+            .to_string();
+        if !if_none_match.precondition_passes(&revision_etag(&revision)) {
+            tracing::info!(
+                "Holding request for up to {wait:?} awaiting a revision newer than {revision}"
+            );
+            _ = timeout(wait, revisions.changed()).await;
+        }
+    }
+    let current_bundle = current_bundle.as_ref().read().await;
+    let etag = revision_etag(current_bundle.bundle.revision());
     headers.typed_insert(etag.clone());
     tracing::info!(
         "Request had If-None-Match of {:?}, current ETag is {:?}",
         if_none_match,
         etag
     );
-    match if_none_match {
-        Some(TypedHeader(if_none_match)) if !if_none_match.precondition_passes(&etag) => {
-            (StatusCode::NOT_MODIFIED, headers, Bytes::new())
+    let Some(TypedHeader(if_none_match)) = if_none_match else {
+        return (StatusCode::OK, headers, current_bundle.file.clone());
+    };
+    if !if_none_match.precondition_passes(&etag) {
+        return (StatusCode::NOT_MODIFIED, headers, Bytes::new());
+    }
+    match current_bundle
+        .deltas
+        .iter()
+        .find(|(revision, _)| !if_none_match.precondition_passes(&revision_etag(revision)))
+    {
+        Some((revision, delta)) => {
+            tracing::info!("Serving delta bundle from {revision}");
+            (StatusCode::OK, headers, delta.clone())
         }
-        _ => (
-            StatusCode::OK,
-            headers,
-            current_bundle.as_ref().read().await.file.clone(),
-        ),
+        None => (StatusCode::OK, headers, current_bundle.file.clone()),
     }
 }
 
-/// Returns an HTTP 200 response when requested.
+/// Parses the duration requested via the 'Prefer: wait=N' header, if present, limited to the
+/// maximum wait
+fn prefer_wait(headers: &HeaderMap, max_wait: Duration) -> Option<Duration> {
+    headers
+        .get_all("prefer")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split([',', ';']))
+        .find_map(|preference| {
+            let (name, value) = preference.split_once('=')?;
+            if !name.trim().eq_ignore_ascii_case("wait") {
+                return None;
+            }
+            value.trim().parse().ok().map(Duration::from_secs)
+        })
+        .map(|wait| wait.min(max_wait))
+}
+
+/// Produces the [`ETag`] corresponding to a bundle revision
+fn revision_etag(revision: &str) -> ETag {
+    ETag::from_str(&format!(r#""{revision}""#)).unwrap()
+}
+
+/// Returns an HTTP 200 response when requested, with a report of the bundle refresh health.
 ///
-/// Failures in the bundle update and serialization result in service crash, so ability to serve this endpoint implies liveness
-async fn health_endpoint() -> impl IntoResponse {
-    StatusCode::OK
+/// Failures in the bundle update and serialization are retried whilst the previous bundle continues to be served, so ability to serve this endpoint implies
+/// liveness, though the service will report as degraded
+async fn health_endpoint(State(status): State<StatusState>) -> impl IntoResponse {
+    (
+        StatusCode::OK,
+        Json(status.refresh_status.read().await.health()),
+    )
+}
+
+/// Returns an HTTP 200 response when every bundle being served has been refreshed recently, or an HTTP 503 response once any has become stale
+async fn ready_endpoint(State(status): State<StatusState>) -> impl IntoResponse {
+    let refresh_status = status.refresh_status.read().await;
+    let status_code = if refresh_status.is_ready(status.max_bundle_age) {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status_code, Json(refresh_status.health()))
+}
+
+/// Returns a report of each bundle being served and the outcome of recent refreshes
+async fn status_endpoint(State(status): State<StatusState>) -> impl IntoResponse {
+    Json(status.refresh_status.read().await.status())
 }
 
 /// Returns a HTTP 404 status code when a non-existant route is queried
@@ -358,15 +1212,26 @@ async fn fallback_endpoint() -> impl IntoResponse {
     StatusCode::NOT_FOUND
 }
 
-/// Outputs the bundle schema as a set of files or to standard output
+/// Outputs the bundle schema, including those of any static data, as a set of files or to
+/// standard output
 fn bundle_schema(args: BundleSchemaArgs) {
-    let schemas = Bundle::<NoMetadata>::schemas()
+    let field_filters = args.field_filters.filters().unwrap();
+    let static_schemas = args.static_data.files().schemas().unwrap();
+    let schemas = Bundle::<NoMetadata>::schemas(&field_filters)
         .into_iter()
-        .map(|(name, schema)| (name, serde_json::to_string_pretty(&schema).unwrap()));
+        .map(|(name, schema)| (name, serde_json::to_string_pretty(&schema).unwrap()))
+        .chain(
+            static_schemas
+                .into_iter()
+                .map(|(name, schema)| (name, serde_json::to_string_pretty(&schema).unwrap())),
+        );
     if let Some(path) = args.path {
         for (name, schema) in schemas {
-            let mut schema_file =
-                File::create(path.clone().join(name).with_extension("json")).unwrap();
+            let schema_path = path.clone().join(name).with_extension("json");
+            if let Some(parent) = schema_path.parent() {
+                std::fs::create_dir_all(parent).unwrap();
+            }
+            let mut schema_file = File::create(schema_path).unwrap();
             schema_file.write_all(schema.as_bytes()).unwrap();
         }
     } else {
@@ -379,3 +1244,96 @@ fn bundle_schema(args: BundleSchemaArgs) {
         )
     }
 }
+
+/// Fetches a single bundle from the permissionables source and any static files, and writes it to
+/// disk as either a gzipped tar archive or an unpacked directory
+async fn build(args: BuildArgs) {
+    let signer = args.signing.signer().unwrap();
+    let field_filters = args.field_filters.filters().unwrap();
+    let options = args.layout.options(field_filters).unwrap();
+    let source = args.source.connect().await.unwrap();
+    let static_data = args.static_data.files();
+    let bundle = Bundle::fetch(NoMetadata, &static_data, &source, &options)
+        .await
+        .unwrap();
+    if args.unpacked {
+        bundle
+            .write_files(signer.as_ref(), |path, data| {
+                let path = args.output.clone().join(path);
+                if let Some(parent) = path.path().parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                Ok(std::fs::write(path.path(), data)?)
+            })
+            .unwrap();
+    } else {
+        std::fs::write(
+            args.output.path(),
+            bundle.to_tar_gz(signer.as_ref()).unwrap(),
+        )
+        .unwrap();
+    }
+    println!(
+        "Wrote bundle with revision {} to {}",
+        bundle.revision(),
+        args.output
+    );
+}
+
+/// Verifies the signature of a bundle archive, exiting with a failure status if it is invalid
+fn verify_bundle(args: VerifyBundleArgs) {
+    let files = read_tar_gz(File::open(args.bundle.path()).unwrap()).unwrap();
+    let key = std::fs::read(args.key.path()).unwrap();
+    match signing::verify(&files, args.algorithm, &key) {
+        Ok(()) => println!("Bundle signature verified"),
+        Err(err) => {
+            eprintln!("Bundle signature invalid: {err}");
+            std::process::exit(1)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::prefer_wait;
+    use axum::http::{HeaderMap, HeaderValue};
+    use std::time::Duration;
+
+    fn wait(preferences: &[&str]) -> Option<Duration> {
+        let mut headers = HeaderMap::new();
+        for preference in preferences {
+            headers.append("prefer", HeaderValue::from_str(preference).unwrap());
+        }
+        prefer_wait(&headers, Duration::from_secs(300))
+    }
+
+    #[test]
+    fn prefer_wait_parsed() {
+        assert_eq!(Some(Duration::from_secs(10)), wait(&["wait=10"]));
+        assert_eq!(
+            Some(Duration::from_secs(10)),
+            wait(&["respond-async, Wait = 10"])
+        );
+        assert_eq!(
+            Some(Duration::from_secs(10)),
+            wait(&["handling=lenient", "wait=10"])
+        );
+        assert_eq!(Some(Duration::ZERO), wait(&["wait=0"]));
+    }
+
+    #[test]
+    fn prefer_wait_limited_to_maximum() {
+        assert_eq!(Some(Duration::from_secs(300)), wait(&["wait=3600"]));
+    }
+
+    #[test]
+    fn prefer_wait_malformed_ignored() {
+        assert_eq!(None, wait(&[]));
+        assert_eq!(None, wait(&["respond-async"]));
+        assert_eq!(None, wait(&["wait"]));
+        assert_eq!(None, wait(&["wait=soon"]));
+        assert_eq!(None, wait(&["wait=-10"]));
+        assert_eq!(None, wait(&["wait=1.5"]));
+        assert_eq!(None, wait(&["timeout=10"]));
+    }
+}
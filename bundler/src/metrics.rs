@@ -0,0 +1,220 @@
+use axum::{
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+use std::{collections::BTreeMap, future::Future, sync::LazyLock, time::Instant};
+
+/// The metrics of the service, exposed for scraping by Prometheus
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+/// A set of Prometheus metrics describing bundle construction and serving
+pub struct Metrics {
+    /// The registry in which all metrics are registered
+    registry: Registry,
+    /// The time taken to fetch each permissionable, labelled by query
+    fetch_duration: HistogramVec,
+    /// The number of failed fetches of each permissionable, labelled by query
+    fetch_failures: IntCounterVec,
+    /// The size of each bundle being served, labelled by path and encoding
+    bundle_size: IntGaugeVec,
+    /// The number of entries in each permissionable of each bundle being served, labelled by path
+    /// and permissionable
+    bundle_entries: IntGaugeVec,
+    /// The number of responses from the bundle endpoint, labelled by status code
+    bundle_responses: IntCounterVec,
+    /// The number of requests rejected for lack of a valid bearer token
+    unauthorized_requests: IntCounter,
+}
+
+impl Metrics {
+    /// Creates and registers each of the [`Metrics`]
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("bundler".to_string()), None).unwrap();
+        let fetch_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "fetch_duration_seconds",
+                "The time taken to fetch each permissionable",
+            ),
+            &["query"],
+        )
+        .unwrap();
+        let fetch_failures = IntCounterVec::new(
+            Opts::new(
+                "fetch_failures_total",
+                "The number of failed fetches of each permissionable",
+            ),
+            &["query"],
+        )
+        .unwrap();
+        let bundle_size = IntGaugeVec::new(
+            Opts::new("bundle_size_bytes", "The size of each bundle being served"),
+            &["bundle", "encoding"],
+        )
+        .unwrap();
+        let bundle_entries = IntGaugeVec::new(
+            Opts::new(
+                "bundle_entries",
+                "The number of entries in each permissionable of each bundle being served",
+            ),
+            &["bundle", "permissionable"],
+        )
+        .unwrap();
+        let bundle_responses = IntCounterVec::new(
+            Opts::new(
+                "bundle_responses_total",
+                "The number of responses from the bundle endpoint",
+            ),
+            &["status"],
+        )
+        .unwrap();
+        let unauthorized_requests = IntCounter::new(
+            "unauthorized_requests_total",
+            "The number of requests rejected for lack of a valid bearer token",
+        )
+        .unwrap();
+        registry.register(Box::new(fetch_duration.clone())).unwrap();
+        registry.register(Box::new(fetch_failures.clone())).unwrap();
+        registry.register(Box::new(bundle_size.clone())).unwrap();
+        registry.register(Box::new(bundle_entries.clone())).unwrap();
+        registry
+            .register(Box::new(bundle_responses.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(unauthorized_requests.clone()))
+            .unwrap();
+        Self {
+            registry,
+            fetch_duration,
+            fetch_failures,
+            bundle_size,
+            bundle_entries,
+            bundle_responses,
+            unauthorized_requests,
+        }
+    }
+
+    /// Awaits the fetch of a permissionable, recording its duration and whether it failed
+    pub async fn time_fetch<T, E>(
+        &self,
+        query: &str,
+        fetch: impl Future<Output = Result<T, E>>,
+    ) -> Result<T, E> {
+        let start = Instant::now();
+        let result = fetch.await;
+        self.fetch_duration
+            .with_label_values(&[query])
+            .observe(start.elapsed().as_secs_f64());
+        if result.is_err() {
+            self.fetch_failures.with_label_values(&[query]).inc();
+        }
+        result
+    }
+
+    /// Records the sizes and entry counts of the bundle being served at the path
+    pub fn record_bundle(
+        &self,
+        path: &str,
+        compressed_size: usize,
+        uncompressed_size: usize,
+        entry_counts: &BTreeMap<String, usize>,
+    ) {
+        self.bundle_size
+            .with_label_values(&[path, "gzip"])
+            .set(compressed_size as i64);
+        self.bundle_size
+            .with_label_values(&[path, "identity"])
+            .set(uncompressed_size as i64);
+        for (permissionable, count) in entry_counts {
+            self.bundle_entries
+                .with_label_values(&[path, permissionable])
+                .set(*count as i64);
+        }
+    }
+
+    /// Records the status code of a response from the bundle endpoint
+    pub fn record_bundle_response(&self, status: StatusCode) {
+        self.bundle_responses
+            .with_label_values(&[status.as_str()])
+            .inc();
+    }
+
+    /// Records a request rejected for lack of a valid bearer token
+    pub fn record_unauthorized(&self) {
+        self.unauthorized_requests.inc();
+    }
+
+    /// Encodes all metrics in the Prometheus text exposition format
+    fn encode(&self) -> Result<Vec<u8>, prometheus::Error> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+/// Returns the [`METRICS`] in the Prometheus text exposition format
+pub async fn metrics_endpoint() -> Response {
+    match METRICS.encode() {
+        Ok(metrics) => (
+            [(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static(prometheus::TEXT_FORMAT),
+            )],
+            metrics,
+        )
+            .into_response(),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    }
+}
+
+/// Records the status code of each response from the bundle endpoint
+pub async fn record_bundle_response(response: Response) -> Response {
+    METRICS.record_bundle_response(response.status());
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Metrics;
+    use axum::http::StatusCode;
+    use std::collections::BTreeMap;
+
+    #[tokio::test]
+    async fn encode_recorded() {
+        let metrics = Metrics::new();
+        metrics
+            .time_fetch("subjects", async { Ok::<_, ()>(()) })
+            .await
+            .unwrap();
+        metrics
+            .time_fetch("sessions", async { Err::<(), _>(()) })
+            .await
+            .unwrap_err();
+        metrics.record_bundle(
+            "/bundle.tar.gz",
+            100,
+            400,
+            &BTreeMap::from([("subjects".to_string(), 2)]),
+        );
+        metrics.record_bundle_response(StatusCode::OK);
+        metrics.record_bundle_response(StatusCode::NOT_MODIFIED);
+        metrics.record_bundle_response(StatusCode::NOT_MODIFIED);
+        metrics.record_unauthorized();
+        let encoded = String::from_utf8(metrics.encode().unwrap()).unwrap();
+        for line in [
+            r#"bundler_fetch_duration_seconds_count{query="subjects"} 1"#,
+            r#"bundler_fetch_failures_total{query="sessions"} 1"#,
+            r#"bundler_bundle_size_bytes{bundle="/bundle.tar.gz",encoding="gzip"} 100"#,
+            r#"bundler_bundle_size_bytes{bundle="/bundle.tar.gz",encoding="identity"} 400"#,
+            r#"bundler_bundle_entries{bundle="/bundle.tar.gz",permissionable="subjects"} 2"#,
+            r#"bundler_bundle_responses_total{status="200"} 1"#,
+            r#"bundler_bundle_responses_total{status="304"} 2"#,
+            "bundler_unauthorized_requests_total 1",
+        ] {
+            assert!(encoded.contains(line), "{line} not in {encoded}");
+        }
+    }
+}
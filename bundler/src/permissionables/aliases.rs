@@ -0,0 +1,98 @@
+use super::subjects::Subjects;
+use crate::filter::FieldFilters;
+use derive_more::{Deref, DerefMut};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A mapping of the alternate identifiers of subjects, such as their email address, to the
+/// subjects they identify
+#[derive(
+    Debug, Default, Deref, DerefMut, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema,
+)]
+pub struct Aliases(BTreeMap<String, String>);
+
+impl Aliases {
+    /// Indexes the aliases of each subject, omitting any shared by more than one subject, and
+    /// omitting or hashing those whose fields are filtered from the subjects
+    pub fn new(subjects: &Subjects, field_filters: &FieldFilters) -> Self {
+        let mut aliases = BTreeMap::new();
+        let mut ambiguous = BTreeSet::new();
+        for (name, subject) in subjects.iter() {
+            for (field, alias) in subject.aliases() {
+                let Some(alias) = field_filters.filter_field("subjects", field, &alias) else {
+                    continue;
+                };
+                if let Some(other) = aliases.insert(alias.clone(), name.clone()) {
+                    if other != *name {
+                        ambiguous.insert(alias);
+                    }
+                }
+            }
+        }
+        for alias in ambiguous {
+            aliases.remove(&alias);
+        }
+        Self(aliases)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Aliases;
+    use crate::{filter::FieldFilters, permissionables::subjects::Subjects};
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn from_subjects() {
+        let subjects = serde_json::from_value::<Subjects>(json!({
+            "foo": {
+                "permissions": [],
+                "proposals": [],
+                "sessions": [],
+                "proposal_roles": {},
+                "session_roles": {},
+                "external_id": "00000000000000000000000000000014",
+                "email": "Foo@Example.ac.uk",
+                "family_name": "Foo",
+                "given_name": "Alice"
+            },
+            "bar": {
+                "permissions": [],
+                "proposals": [],
+                "sessions": [],
+                "proposal_roles": {},
+                "session_roles": {},
+                "email": "shared@example.ac.uk"
+            },
+            "baz": {
+                "permissions": [],
+                "proposals": [],
+                "sessions": [],
+                "proposal_roles": {},
+                "session_roles": {},
+                "email": "shared@example.ac.uk"
+            }
+        }))
+        .unwrap();
+        let expected = Aliases(BTreeMap::from([
+            (
+                "00000000000000000000000000000014".to_string(),
+                "foo".to_string(),
+            ),
+            ("foo@example.ac.uk".to_string(), "foo".to_string()),
+        ]));
+        assert_eq!(expected, Aliases::new(&subjects, &FieldFilters::default()));
+        let filters = FieldFilters::new(
+            vec![],
+            vec!["subjects.external_id".parse().unwrap()],
+            vec![],
+        );
+        let expected = Aliases(BTreeMap::from([(
+            "foo@example.ac.uk".to_string(),
+            "foo".to_string(),
+        )]));
+        assert_eq!(expected, Aliases::new(&subjects, &filters));
+    }
+}
@@ -1,19 +1,23 @@
+use super::insert_sorted;
 use derive_more::{Deref, DerefMut};
+use futures::TryStreamExt;
 use schemars::JsonSchema;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use sqlx::{query_as, MySqlPool};
 use std::collections::BTreeMap;
 use tracing::instrument;
 
 /// A mapping of beamlines to their various attributes
-#[derive(Debug, Default, Deref, DerefMut, PartialEq, Eq, Hash, Serialize, JsonSchema)]
+#[derive(
+    Debug, Default, Clone, Deref, DerefMut, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema,
+)]
 pub struct Beamlines(BTreeMap<String, Beamline>);
 
 impl Beamlines {
     /// Fetches [`Beamlines`] from ISPyB
     #[instrument(name = "fetch_beamlines")]
     pub async fn fetch(ispyb_pool: &MySqlPool) -> Result<Self, sqlx::Error> {
-        let session_rows = query_as!(
+        query_as!(
             RawBeamlineRow,
             "
             SELECT
@@ -23,17 +27,16 @@ impl Beamlines {
                 BLSession
             "
         )
-        .fetch_all(ispyb_pool)
-        .await?;
-
-        Ok(session_rows.into_iter().collect())
+        .fetch(ispyb_pool)
+        .try_collect()
+        .await
     }
 }
 
 /// The various attributes of a beamline
-#[derive(Debug, Default, PartialEq, Eq, Hash, Serialize, JsonSchema)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
 pub struct Beamline {
-    /// The sessions which occured on this beamline
+    /// The sessions which occured on this beamline, in ascending order
     sessions: Vec<u32>,
 }
 
@@ -65,19 +68,16 @@ impl TryFrom<RawBeamlineRow> for BeamlineRow {
     }
 }
 
-impl FromIterator<RawBeamlineRow> for Beamlines {
-    fn from_iter<T: IntoIterator<Item = RawBeamlineRow>>(iter: T) -> Self {
-        let mut beamlines = Self::default();
+impl Extend<RawBeamlineRow> for Beamlines {
+    fn extend<T: IntoIterator<Item = RawBeamlineRow>>(&mut self, iter: T) {
         for beamline_row in iter {
             if let Ok(beamline) = BeamlineRow::try_from(beamline_row) {
-                beamlines
-                    .entry(beamline.beamline)
-                    .or_default()
-                    .sessions
-                    .push(beamline.session_id)
+                insert_sorted(
+                    &mut self.entry(beamline.beamline).or_default().sessions,
+                    beamline.session_id,
+                );
             }
         }
-        beamlines
     }
 }
 
@@ -110,6 +110,7 @@ mod tests {
         );
         expected.insert("b13".to_string(), Beamline { sessions: vec![42] });
         expected.insert("p99".to_string(), Beamline { sessions: vec![43] });
+        expected.insert("b21".to_string(), Beamline { sessions: vec![45] });
         assert_eq!(expected, beamlines.0);
     }
 }
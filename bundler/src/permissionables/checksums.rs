@@ -0,0 +1,225 @@
+use sqlx::{query_as, MySqlPool};
+use std::collections::BTreeMap;
+use tracing::instrument;
+
+/// A checksum of each ISPyB table from which permissionables are fetched, which differs whenever
+/// any of the rows, or the columns of them which are fetched, are added, removed or changed
+///
+/// These are computed in the database and returned as a single row per table, such that they are
+/// far cheaper to fetch than the permissionables themselves
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableChecksums(BTreeMap<String, TableChecksum>);
+
+/// The number of rows in a table and a checksum of their contents
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TableChecksum {
+    /// The number of rows in the table
+    row_count: i64,
+    /// The sum, modulo 2^64, of the leading 64 bits of the SHA-256 digest of the fetched columns
+    /// of each row
+    checksum: u64,
+}
+
+impl TableChecksums {
+    /// The checksums of only the given tables, such that changes to others are disregarded
+    pub fn of_tables(&self, tables: &[&str]) -> Self {
+        Self(
+            self.0
+                .iter()
+                .filter(|(table, _)| tables.contains(&table.as_str()))
+                .map(|(table, checksum)| (table.clone(), checksum.clone()))
+                .collect(),
+        )
+    }
+
+    /// Fetches [`TableChecksums`] from ISPyB
+    ///
+    /// Each row is digested with SHA-256, rather than a linear checksum such as CRC32, such that
+    /// changes which swap values between rows do not cancel out
+    #[instrument(name = "fetch_table_checksums")]
+    pub async fn fetch(ispyb_pool: &MySqlPool) -> Result<Self, sqlx::Error> {
+        let checksum_rows = query_as!(
+            TableChecksumRow,
+            "
+            SELECT
+                'BLSession' AS `table_name!`,
+                COUNT(*) AS `row_count!`,
+                CAST(COALESCE(MOD(SUM(CAST(CONV(LEFT(SHA2(JSON_ARRAY(sessionId, proposalId, visit_number, beamLineName, startDate, endDate, scheduled, archived), 256), 16), 16, 10) AS UNSIGNED)), 18446744073709551616), 0) AS UNSIGNED) AS `checksum!`
+            FROM BLSession
+            UNION ALL SELECT
+                'Proposal',
+                COUNT(*),
+                CAST(COALESCE(MOD(SUM(CAST(CONV(LEFT(SHA2(JSON_ARRAY(proposalId, proposalCode, proposalNumber, title, state, HEX(externalId)), 256), 16), 16, 10) AS UNSIGNED)), 18446744073709551616), 0) AS UNSIGNED)
+            FROM Proposal
+            UNION ALL SELECT
+                'Person',
+                COUNT(*),
+                CAST(COALESCE(MOD(SUM(CAST(CONV(LEFT(SHA2(JSON_ARRAY(personId, login, HEX(externalId), emailAddress, familyName, givenName), 256), 16), 16, 10) AS UNSIGNED)), 18446744073709551616), 0) AS UNSIGNED)
+            FROM Person
+            UNION ALL SELECT
+                'ProposalHasPerson',
+                COUNT(*),
+                CAST(COALESCE(MOD(SUM(CAST(CONV(LEFT(SHA2(JSON_ARRAY(proposalId, personId, role), 256), 16), 16, 10) AS UNSIGNED)), 18446744073709551616), 0) AS UNSIGNED)
+            FROM ProposalHasPerson
+            UNION ALL SELECT
+                'Session_has_Person',
+                COUNT(*),
+                CAST(COALESCE(MOD(SUM(CAST(CONV(LEFT(SHA2(JSON_ARRAY(sessionId, personId, role), 256), 16), 16, 10) AS UNSIGNED)), 18446744073709551616), 0) AS UNSIGNED)
+            FROM Session_has_Person
+            UNION ALL SELECT
+                'UserGroup',
+                COUNT(*),
+                CAST(COALESCE(MOD(SUM(CAST(CONV(LEFT(SHA2(JSON_ARRAY(userGroupId), 256), 16), 16, 10) AS UNSIGNED)), 18446744073709551616), 0) AS UNSIGNED)
+            FROM UserGroup
+            UNION ALL SELECT
+                'UserGroup_has_Person',
+                COUNT(*),
+                CAST(COALESCE(MOD(SUM(CAST(CONV(LEFT(SHA2(JSON_ARRAY(userGroupId, personId), 256), 16), 16, 10) AS UNSIGNED)), 18446744073709551616), 0) AS UNSIGNED)
+            FROM UserGroup_has_Person
+            UNION ALL SELECT
+                'UserGroup_has_Permission',
+                COUNT(*),
+                CAST(COALESCE(MOD(SUM(CAST(CONV(LEFT(SHA2(JSON_ARRAY(userGroupId, permissionId), 256), 16), 16, 10) AS UNSIGNED)), 18446744073709551616), 0) AS UNSIGNED)
+            FROM UserGroup_has_Permission
+            UNION ALL SELECT
+                'Permission',
+                COUNT(*),
+                CAST(COALESCE(MOD(SUM(CAST(CONV(LEFT(SHA2(JSON_ARRAY(permissionId, type), 256), 16), 16, 10) AS UNSIGNED)), 18446744073709551616), 0) AS UNSIGNED)
+            FROM Permission
+            "
+        )
+        .fetch_all(ispyb_pool)
+        .await?;
+
+        Ok(Self(
+            checksum_rows
+                .into_iter()
+                .map(|row| {
+                    (
+                        row.table_name,
+                        TableChecksum {
+                            row_count: row.row_count,
+                            checksum: row.checksum,
+                        },
+                    )
+                })
+                .collect(),
+        ))
+    }
+}
+
+/// A row from ISPyB summarising the contents of a table
+struct TableChecksumRow {
+    /// The name of the table
+    table_name: String,
+    /// The number of rows in the table
+    row_count: i64,
+    /// The sum, modulo 2^64, of the leading 64 bits of the SHA-256 digest of the fetched columns
+    /// of each row
+    checksum: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TableChecksum, TableChecksums};
+    use crate::permissionables::Part;
+    use sqlx::{query, MySqlPool};
+    use std::collections::BTreeMap;
+
+    fn checksums(person_checksum: u64) -> TableChecksums {
+        TableChecksums(BTreeMap::from([
+            (
+                "BLSession".to_string(),
+                TableChecksum {
+                    row_count: 5,
+                    checksum: 42,
+                },
+            ),
+            (
+                "Person".to_string(),
+                TableChecksum {
+                    row_count: 2,
+                    checksum: person_checksum,
+                },
+            ),
+        ]))
+    }
+
+    #[test]
+    fn unchanged_by_other_tables() {
+        let before = checksums(7);
+        let after = checksums(8);
+        assert_eq!(
+            before.of_tables(Part::Beamlines.tables()),
+            after.of_tables(Part::Beamlines.tables())
+        );
+        assert_ne!(
+            before.of_tables(Part::Subjects.tables()),
+            after.of_tables(Part::Subjects.tables())
+        );
+    }
+
+    #[sqlx::test(migrations = "tests/migrations")]
+    async fn fetch_empty(ispyb_pool: MySqlPool) {
+        let checksums = TableChecksums::fetch(&ispyb_pool).await.unwrap();
+        assert_eq!(9, checksums.0.len());
+        assert!(checksums
+            .0
+            .values()
+            .all(|checksum| checksum.row_count == 0 && checksum.checksum == 0));
+    }
+
+    #[sqlx::test(
+        migrations = "tests/migrations",
+        fixtures(
+            "../../tests/fixtures/session_membership.sql",
+            "../../tests/fixtures/persons.sql"
+        )
+    )]
+    async fn changed_by_update(ispyb_pool: MySqlPool) {
+        let before = TableChecksums::fetch(&ispyb_pool).await.unwrap();
+        assert_eq!(before, TableChecksums::fetch(&ispyb_pool).await.unwrap());
+        query("UPDATE Session_has_Person SET role = NULL WHERE sessionId = 40")
+            .execute(&ispyb_pool)
+            .await
+            .unwrap();
+        let after = TableChecksums::fetch(&ispyb_pool).await.unwrap();
+        assert_ne!(before, after);
+        assert_eq!(
+            before.0["Session_has_Person"].row_count,
+            after.0["Session_has_Person"].row_count
+        );
+        assert_eq!(before.0["Person"], after.0["Person"]);
+    }
+
+    #[sqlx::test(
+        migrations = "tests/migrations",
+        fixtures(
+            "../../tests/fixtures/session_membership.sql",
+            "../../tests/fixtures/persons.sql"
+        )
+    )]
+    async fn changed_by_swap(ispyb_pool: MySqlPool) {
+        let before = TableChecksums::fetch(&ispyb_pool).await.unwrap();
+        query(
+            "UPDATE Session_has_Person SET personId = IF(personId = 20, 21, 20) WHERE sessionId IN (40, 43)",
+        )
+        .execute(&ispyb_pool)
+        .await
+        .unwrap();
+        let swapped_persons = TableChecksums::fetch(&ispyb_pool).await.unwrap();
+        assert_ne!(before, swapped_persons);
+        query(
+            "UPDATE Session_has_Person SET role = IF(sessionId = 40, 'Local Contact', 'Principal Investigator') WHERE sessionId IN (40, 43)",
+        )
+        .execute(&ispyb_pool)
+        .await
+        .unwrap();
+        let swapped_roles = TableChecksums::fetch(&ispyb_pool).await.unwrap();
+        assert_ne!(swapped_persons, swapped_roles);
+        assert_eq!(
+            before.0["Session_has_Person"].row_count,
+            swapped_roles.0["Session_has_Person"].row_count
+        );
+    }
+}
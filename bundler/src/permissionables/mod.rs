@@ -1,8 +1,185 @@
+/// A mapping of alternate subject identifiers to their subjects
+pub mod aliases;
 /// A mapping of beamlines to their attributes
 pub mod beamlines;
+/// Checksums of the ISPyB tables from which permissionables are fetched, for change detection
+pub mod checksums;
 /// A mapping of proposals to their attributes
 pub mod proposals;
 /// A mapping of sessions to their attributes
 pub mod sessions;
 /// A mapping of subjects to their attributes
 pub mod subjects;
+/// A mapping of canonical visit identifiers to their sessions
+pub mod visits;
+
+use self::{beamlines::Beamlines, proposals::Proposals, sessions::Sessions, subjects::Subjects};
+use crate::metrics::METRICS;
+use serde::{Deserialize, Serialize};
+use sqlx::MySqlPool;
+use std::{collections::BTreeSet, future::Future, sync::Arc};
+use tokio::try_join;
+use tracing::instrument;
+
+/// The complete set of permissionables from which a bundle is built
+///
+/// Each part is shared, such that cloning the permissionables to replace some of their parts does
+/// not copy the remainder
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Permissionables {
+    /// A mapping of subjects to their various attributes
+    pub subjects: Arc<Subjects>,
+    /// A mapping of sessions to their various attributes
+    pub sessions: Arc<Sessions>,
+    /// A mapping of proposals to their various attributes
+    pub proposals: Arc<Proposals>,
+    /// A mapping of beamlines to their various attributes
+    pub beamlines: Arc<Beamlines>,
+}
+
+/// One of the separately fetched parts of the [`Permissionables`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Part {
+    /// The [`Subjects`]
+    Subjects,
+    /// The [`Sessions`]
+    Sessions,
+    /// The [`Proposals`]
+    Proposals,
+    /// The [`Beamlines`]
+    Beamlines,
+}
+
+impl Part {
+    /// Every part of the [`Permissionables`]
+    pub const ALL: [Self; 4] = [
+        Self::Subjects,
+        Self::Sessions,
+        Self::Proposals,
+        Self::Beamlines,
+    ];
+
+    /// The name of the part, as used in the bundle
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Subjects => "subjects",
+            Self::Sessions => "sessions",
+            Self::Proposals => "proposals",
+            Self::Beamlines => "beamlines",
+        }
+    }
+
+    /// The part with the given name, if there is one
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|part| part.name() == name)
+    }
+
+    /// The ISPyB tables from which the part is fetched
+    pub fn tables(self) -> &'static [&'static str] {
+        match self {
+            Self::Subjects => &[
+                "Person",
+                "UserGroup",
+                "UserGroup_has_Person",
+                "UserGroup_has_Permission",
+                "Permission",
+                "Proposal",
+                "ProposalHasPerson",
+                "Session_has_Person",
+            ],
+            Self::Sessions | Self::Proposals => &["BLSession", "Proposal"],
+            Self::Beamlines => &["BLSession"],
+        }
+    }
+}
+
+impl Permissionables {
+    /// Fetches [`Permissionables`] from ISPyB
+    #[instrument(name = "fetch_permissionables")]
+    pub async fn fetch(ispyb_pool: &MySqlPool) -> Result<Self, sqlx::Error> {
+        let mut permissionables = Self::default();
+        permissionables
+            .fetch_parts(ispyb_pool, &BTreeSet::from(Part::ALL))
+            .await?;
+        Ok(permissionables)
+    }
+
+    /// Fetches the given parts of the [`Permissionables`] from ISPyB, replacing those held
+    #[instrument(name = "fetch_permissionable_parts", skip(self))]
+    pub async fn fetch_parts(
+        &mut self,
+        ispyb_pool: &MySqlPool,
+        parts: &BTreeSet<Part>,
+    ) -> Result<(), sqlx::Error> {
+        let (subjects, sessions, proposals, beamlines) = try_join!(
+            fetch_part(parts, Part::Subjects, Subjects::fetch(ispyb_pool)),
+            fetch_part(parts, Part::Sessions, Sessions::fetch(ispyb_pool)),
+            fetch_part(parts, Part::Proposals, Proposals::fetch(ispyb_pool)),
+            fetch_part(parts, Part::Beamlines, Beamlines::fetch(ispyb_pool)),
+        )?;
+        self.replace_parts(
+            Self {
+                subjects: Arc::new(subjects.unwrap_or_default()),
+                sessions: Arc::new(sessions.unwrap_or_default()),
+                proposals: Arc::new(proposals.unwrap_or_default()),
+                beamlines: Arc::new(beamlines.unwrap_or_default()),
+            },
+            parts,
+        );
+        Ok(())
+    }
+
+    /// Replaces the given parts with those of newer [`Permissionables`], keeping the remainder
+    pub fn replace_parts(&mut self, mut newer: Self, parts: &BTreeSet<Part>) {
+        for part in parts {
+            match part {
+                Part::Subjects => self.subjects = std::mem::take(&mut newer.subjects),
+                Part::Sessions => self.sessions = std::mem::take(&mut newer.sessions),
+                Part::Proposals => self.proposals = std::mem::take(&mut newer.proposals),
+                Part::Beamlines => self.beamlines = std::mem::take(&mut newer.beamlines),
+            }
+        }
+    }
+}
+
+/// Inserts an item into a list kept in ascending order and without duplicates, such that the list
+/// is independent of the order in which rows are fetched
+fn insert_sorted<T: Ord>(list: &mut Vec<T>, item: T) {
+    if let Err(index) = list.binary_search(&item) {
+        list.insert(index, item);
+    }
+}
+
+/// Awaits the fetch of a part, recording its duration, if it is one of those requested
+async fn fetch_part<T>(
+    parts: &BTreeSet<Part>,
+    part: Part,
+    fetch: impl Future<Output = Result<T, sqlx::Error>>,
+) -> Result<Option<T>, sqlx::Error> {
+    if parts.contains(&part) {
+        Ok(Some(METRICS.time_fetch(part.name(), fetch).await?))
+    } else {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::insert_sorted;
+
+    #[test]
+    fn insert_sorted_independent_of_order() {
+        for items in [
+            [41, 44, 45, 44],
+            [45, 44, 41, 44],
+            [44, 41, 44, 45],
+            [44, 44, 45, 41],
+        ] {
+            let mut list = Vec::new();
+            for item in items {
+                insert_sorted(&mut list, item);
+            }
+            assert_eq!(vec![41, 44, 45], list);
+        }
+    }
+}
@@ -1,23 +1,29 @@
 use derive_more::{Deref, DerefMut};
+use futures::TryStreamExt;
 use schemars::JsonSchema;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use sqlx::{query_as, MySqlPool};
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, str::FromStr};
 use tracing::instrument;
 
 /// A mapping of proposals to their various attributes
-#[derive(Debug, Default, Deref, DerefMut, PartialEq, Eq, Hash, Serialize, JsonSchema)]
+#[derive(
+    Debug, Default, Clone, Deref, DerefMut, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema,
+)]
 pub struct Proposals(BTreeMap<u32, Proposal>);
 
 impl Proposals {
     /// Fetches [`Proposals`] from ISPyB
     #[instrument(name = "fetch_proposals")]
     pub async fn fetch(ispyb_pool: &MySqlPool) -> Result<Self, sqlx::Error> {
-        let proposal_rows = query_as!(
+        query_as!(
             RawProposalRow,
             "
             SELECT
+                proposalCode as proposal_code,
                 proposalNumber as proposal_number,
+                title,
+                state,
                 visit_number,
                 sessionId as session_id
             FROM
@@ -27,24 +33,62 @@ impl Proposals {
                 Proposal.externalId IS NOT NULL
             "
         )
-        .fetch_all(ispyb_pool)
-        .await?;
-
-        Ok(proposal_rows.into_iter().collect())
+        .fetch(ispyb_pool)
+        .try_collect()
+        .await
     }
 }
 
 /// The various attributes of a proposal
-#[derive(Debug, Default, PartialEq, Eq, Hash, Serialize, JsonSchema)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
 pub struct Proposal {
+    /// The code of the proposal, e.g. "cm" in "cm12345"
+    proposal_code: String,
+    /// The title of the proposal, if it has one
+    title: Option<String>,
+    /// The lifecycle state of the proposal
+    state: ProposalState,
     /// The sessions which took place within the proposal
     sessions: BTreeMap<u32, u32>,
 }
 
+/// The lifecycle state of a proposal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+pub enum ProposalState {
+    /// The proposal is active
+    Open,
+    /// The proposal has concluded
+    Closed,
+    /// The proposal was withdrawn
+    Cancelled,
+    /// The state of the proposal was not recorded, or was not recognised, so it should be treated
+    /// as neither open nor closed
+    Unknown,
+}
+
+impl FromStr for ProposalState {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Open" => Ok(Self::Open),
+            "Closed" => Ok(Self::Closed),
+            "Cancelled" => Ok(Self::Cancelled),
+            _ => Err(anyhow::anyhow!("Unknown proposal state {s}")),
+        }
+    }
+}
+
 /// A row from ISPyB detailing the sessions in a proposal
 struct ProposalRow {
+    /// The proposal code
+    proposal_code: String,
     /// The proposal number
     proposal_number: u32,
+    /// The title of the proposal
+    title: Option<String>,
+    /// The lifecycle state of the proposal
+    state: ProposalState,
     /// The number of the visit on the proposal
     visit_number: u32,
     /// An opaque identifier of the session
@@ -53,7 +97,10 @@ struct ProposalRow {
 
 #[allow(clippy::missing_docs_in_private_items)]
 struct RawProposalRow {
+    proposal_code: Option<String>,
     proposal_number: Option<String>,
+    title: Option<String>,
+    state: Option<String>,
     visit_number: Option<u32>,
     session_id: u32,
 }
@@ -63,35 +110,52 @@ impl TryFrom<RawProposalRow> for ProposalRow {
 
     fn try_from(value: RawProposalRow) -> Result<Self, Self::Error> {
         Ok(Self {
+            proposal_code: value
+                .proposal_code
+                .ok_or(anyhow::anyhow!("Proposal Code was NULL"))?,
             proposal_number: value
                 .proposal_number
                 .ok_or(anyhow::anyhow!("Proposal Number was NULL"))?
                 .parse()?,
+            title: value.title,
+            state: match value.state {
+                Some(state) => state.parse().unwrap_or_else(|err| {
+                    tracing::warn!("{err} of session {}", value.session_id);
+                    ProposalState::Unknown
+                }),
+                None => ProposalState::Unknown,
+            },
             visit_number: value.visit_number.unwrap_or_default(),
             session_id: value.session_id,
         })
     }
 }
 
-impl FromIterator<RawProposalRow> for Proposals {
-    fn from_iter<T: IntoIterator<Item = RawProposalRow>>(iter: T) -> Self {
-        let mut proposals = Self::default();
+impl Extend<RawProposalRow> for Proposals {
+    fn extend<T: IntoIterator<Item = RawProposalRow>>(&mut self, iter: T) {
         for proposal_row in iter {
-            if let Ok(proposal_row) = ProposalRow::try_from(proposal_row) {
-                proposals
-                    .entry(proposal_row.proposal_number)
-                    .or_default()
-                    .sessions
-                    .insert(proposal_row.visit_number, proposal_row.session_id);
+            let session_id = proposal_row.session_id;
+            match ProposalRow::try_from(proposal_row) {
+                Ok(proposal_row) => {
+                    self.entry(proposal_row.proposal_number)
+                        .or_insert_with(|| Proposal {
+                            proposal_code: proposal_row.proposal_code,
+                            title: proposal_row.title,
+                            state: proposal_row.state,
+                            sessions: BTreeMap::new(),
+                        })
+                        .sessions
+                        .insert(proposal_row.visit_number, proposal_row.session_id);
+                }
+                Err(err) => tracing::warn!("Omitting proposal of session {session_id}: {err}"),
             }
         }
-        proposals
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Proposal, Proposals};
+    use super::{Proposal, ProposalState, Proposals, RawProposalRow};
     use sqlx::MySqlPool;
     use std::collections::BTreeMap;
 
@@ -115,15 +179,69 @@ mod tests {
         expected.insert(
             10030,
             Proposal {
+                proposal_code: "cm".to_string(),
+                title: Some("Tomography of battery electrodes".to_string()),
+                state: ProposalState::Open,
                 sessions: BTreeMap::from([(10, 40), (11, 41), (12, 42)]),
             },
         );
         expected.insert(
             10031,
             Proposal {
+                proposal_code: "mx".to_string(),
+                title: Some("Structures of membrane proteins".to_string()),
+                state: ProposalState::Closed,
                 sessions: BTreeMap::from([(10, 43), (11, 44)]),
             },
         );
+        expected.insert(
+            10032,
+            Proposal {
+                proposal_code: "sw".to_string(),
+                title: None,
+                state: ProposalState::Cancelled,
+                sessions: BTreeMap::from([(1, 45)]),
+            },
+        );
         assert_eq!(expected, beamlines.0);
     }
+
+    #[test]
+    fn parse_state() {
+        assert_eq!(ProposalState::Open, "Open".parse().unwrap());
+        assert_eq!(ProposalState::Closed, "Closed".parse().unwrap());
+        assert_eq!(ProposalState::Cancelled, "Cancelled".parse().unwrap());
+        assert!("Pending".parse::<ProposalState>().is_err());
+    }
+
+    #[test]
+    fn unrecorded_state_unknown() {
+        let mut proposals = Proposals::default();
+        proposals.extend(
+            ["Open", "Pending"]
+                .into_iter()
+                .map(Some)
+                .chain([None])
+                .zip(1..)
+                .map(|(state, proposal_number)| RawProposalRow {
+                    proposal_code: Some("cm".to_string()),
+                    proposal_number: Some(proposal_number.to_string()),
+                    title: None,
+                    state: state.map(str::to_string),
+                    visit_number: Some(1),
+                    session_id: proposal_number,
+                }),
+        );
+        assert_eq!(
+            vec![
+                ProposalState::Open,
+                ProposalState::Unknown,
+                ProposalState::Unknown
+            ],
+            proposals
+                .values()
+                .map(|proposal| proposal.state)
+                .collect::<Vec<_>>()
+        );
+    }
 }
@@ -1,67 +1,114 @@
 use derive_more::{Deref, DerefMut};
+use futures::TryStreamExt;
 use schemars::JsonSchema;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use sqlx::{query_as, MySqlPool};
 use std::collections::BTreeMap;
+use time::{OffsetDateTime, PrimitiveDateTime};
 use tracing::instrument;
 
 /// A mapping of sessions to their various attributes
-#[derive(Debug, Default, Deref, DerefMut, PartialEq, Eq, Hash, Serialize, JsonSchema)]
+#[derive(
+    Debug, Default, Clone, Deref, DerefMut, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema,
+)]
 pub struct Sessions(BTreeMap<u32, Session>);
 
 impl Sessions {
     /// Fetches [`Sessions`] from ISPyB
     #[instrument(name = "fetch_sessions")]
     pub async fn fetch(ispyb_pool: &MySqlPool) -> Result<Self, sqlx::Error> {
-        let session_rows = query_as!(
+        query_as!(
             RawSessionRow,
             "
             SELECT
                 sessionId as session_id,
+                proposalCode as proposal_code,
                 proposalNumber as proposal_number,
                 visit_number,
-                beamLineName as beamline
+                beamLineName as beamline,
+                startDate as start_date,
+                endDate as end_date,
+                scheduled,
+                archived
             FROM
                 BLSession
                 JOIN Proposal USING (proposalId)
             "
         )
-        .fetch_all(ispyb_pool)
-        .await?;
-
-        Ok(session_rows.into_iter().collect())
+        .fetch(ispyb_pool)
+        .try_collect()
+        .await
     }
 }
 
 /// The various attributes of a session
-#[derive(Debug, Default, PartialEq, Eq, Hash, Serialize, JsonSchema)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
 pub struct Session {
+    /// The code of the proposal this session belongs to
+    proposal_code: String,
     /// The number of the proposal this session belongs to
     proposal_number: u32,
     /// The number of the visit within the proposal this session belongs to
     visit_number: u32,
     /// The beamline the session took place on
     beamline: String,
+    /// When the session starts, if known
+    #[serde(with = "time::serde::rfc3339::option")]
+    #[schemars(with = "Option<String>")]
+    start_date: Option<OffsetDateTime>,
+    /// When the session ends, or none if it is open-ended
+    #[serde(with = "time::serde::rfc3339::option")]
+    #[schemars(with = "Option<String>")]
+    end_date: Option<OffsetDateTime>,
+    /// Whether the session has been scheduled
+    scheduled: bool,
+    /// Whether the data collected in the session has been archived
+    archived: bool,
+}
+
+impl Session {
+    /// The canonical identifier of the visit, e.g. "cm12345-3"
+    pub fn visit(&self) -> String {
+        format!(
+            "{}{}-{}",
+            self.proposal_code, self.proposal_number, self.visit_number
+        )
+    }
 }
 
 /// A row from ISPyB detailing the beamline a session took place on
 struct SessionRow {
     /// An opaque identifier of the session
     session_id: u32,
+    /// The proposal code of the visit
+    proposal_code: String,
     /// The proposal number of the visit
     proposal_number: u32,
     /// The number of the visit within the proposal
     visit_number: u32,
     /// The beamline the session took place on
     beamline: String,
+    /// When the session starts, if known
+    start_date: Option<OffsetDateTime>,
+    /// When the session ends, if known
+    end_date: Option<OffsetDateTime>,
+    /// Whether the session has been scheduled
+    scheduled: bool,
+    /// Whether the data collected in the session has been archived
+    archived: bool,
 }
 
 #[allow(clippy::missing_docs_in_private_items)]
 struct RawSessionRow {
     session_id: u32,
+    proposal_code: Option<String>,
     proposal_number: Option<String>,
     visit_number: Option<u32>,
     beamline: Option<String>,
+    start_date: Option<PrimitiveDateTime>,
+    end_date: Option<PrimitiveDateTime>,
+    scheduled: Option<i8>,
+    archived: Option<i8>,
 }
 
 impl TryFrom<RawSessionRow> for SessionRow {
@@ -70,40 +117,52 @@ impl TryFrom<RawSessionRow> for SessionRow {
     fn try_from(value: RawSessionRow) -> Result<Self, Self::Error> {
         Ok(Self {
             session_id: value.session_id,
+            proposal_code: value
+                .proposal_code
+                .ok_or(anyhow::anyhow!("Proposal code was NULL"))?,
             proposal_number: value
                 .proposal_number
                 .ok_or(anyhow::anyhow!("Proposal number was NULL"))?
                 .parse()?,
             visit_number: value.visit_number.unwrap_or_default(),
             beamline: value.beamline.ok_or(anyhow::anyhow!("Beamline was NULL"))?,
+            start_date: value.start_date.map(PrimitiveDateTime::assume_utc),
+            end_date: value.end_date.map(PrimitiveDateTime::assume_utc),
+            scheduled: value.scheduled.is_some_and(|scheduled| scheduled != 0),
+            archived: value.archived.is_some_and(|archived| archived != 0),
         })
     }
 }
 
-impl FromIterator<RawSessionRow> for Sessions {
-    fn from_iter<T: IntoIterator<Item = RawSessionRow>>(iter: T) -> Self {
-        let mut sessions = Self::default();
+impl Extend<RawSessionRow> for Sessions {
+    fn extend<T: IntoIterator<Item = RawSessionRow>>(&mut self, iter: T) {
         for session_row in iter {
             if let Ok(session_row) = SessionRow::try_from(session_row) {
-                sessions.insert(
+                self.insert(
                     session_row.session_id,
                     Session {
+                        proposal_code: session_row.proposal_code,
                         proposal_number: session_row.proposal_number,
                         visit_number: session_row.visit_number,
                         beamline: session_row.beamline,
+                        start_date: session_row.start_date,
+                        end_date: session_row.end_date,
+                        scheduled: session_row.scheduled,
+                        archived: session_row.archived,
                     },
                 );
             }
         }
-        sessions
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::{Session, Sessions};
+    use serde_json::{json, Value};
     use sqlx::MySqlPool;
     use std::collections::BTreeMap;
+    use time::macros::datetime;
 
     #[sqlx::test(migrations = "tests/migrations")]
     async fn fetch_empty(ispyb_pool: MySqlPool) {
@@ -125,43 +184,124 @@ mod tests {
         expected.insert(
             40,
             Session {
+                proposal_code: "cm".to_string(),
                 proposal_number: 10030,
                 visit_number: 10,
                 beamline: "i12".to_string(),
+                start_date: Some(datetime!(2023-01-10 09:00 UTC)),
+                end_date: Some(datetime!(2023-01-12 09:00 UTC)),
+                scheduled: true,
+                archived: true,
             },
         );
         expected.insert(
             41,
             Session {
+                proposal_code: "cm".to_string(),
                 proposal_number: 10030,
                 visit_number: 11,
                 beamline: "i22".to_string(),
+                start_date: Some(datetime!(2024-03-01 09:00 UTC)),
+                end_date: None,
+                scheduled: true,
+                archived: false,
             },
         );
         expected.insert(
             42,
             Session {
+                proposal_code: "cm".to_string(),
                 proposal_number: 10030,
                 visit_number: 12,
                 beamline: "b13".to_string(),
+                start_date: Some(datetime!(2024-05-01 09:00 UTC)),
+                end_date: Some(datetime!(2024-05-03 09:00 UTC)),
+                scheduled: false,
+                archived: false,
             },
         );
         expected.insert(
             43,
             Session {
+                proposal_code: "mx".to_string(),
                 proposal_number: 10031,
                 visit_number: 10,
                 beamline: "p99".to_string(),
+                start_date: None,
+                end_date: None,
+                scheduled: false,
+                archived: false,
             },
         );
         expected.insert(
             44,
             Session {
+                proposal_code: "mx".to_string(),
                 proposal_number: 10031,
                 visit_number: 11,
                 beamline: "i22".to_string(),
+                start_date: Some(datetime!(2023-06-01 09:00 UTC)),
+                end_date: Some(datetime!(2023-06-02 09:00 UTC)),
+                scheduled: true,
+                archived: false,
+            },
+        );
+        expected.insert(
+            45,
+            Session {
+                proposal_code: "sw".to_string(),
+                proposal_number: 10032,
+                visit_number: 1,
+                beamline: "b21".to_string(),
+                start_date: None,
+                end_date: None,
+                scheduled: false,
+                archived: false,
             },
         );
         assert_eq!(expected, sessions.0);
     }
+
+    #[test]
+    fn serialize_dates() {
+        let ended = Session {
+            proposal_code: "cm".to_string(),
+            proposal_number: 10030,
+            visit_number: 10,
+            beamline: "i12".to_string(),
+            start_date: Some(datetime!(2023-01-10 09:00 UTC)),
+            end_date: Some(datetime!(2023-01-12 09:00 UTC)),
+            scheduled: true,
+            archived: true,
+        };
+        let open_ended = Session {
+            proposal_code: "cm".to_string(),
+            proposal_number: 10030,
+            visit_number: 11,
+            beamline: "i22".to_string(),
+            start_date: Some(datetime!(2024-03-01 09:00 UTC)),
+            end_date: None,
+            scheduled: true,
+            archived: false,
+        };
+        assert_eq!(
+            json!({
+                "proposal_code": "cm",
+                "proposal_number": 10030,
+                "visit_number": 10,
+                "beamline": "i12",
+                "start_date": "2023-01-10T09:00:00Z",
+                "end_date": "2023-01-12T09:00:00Z",
+                "scheduled": true,
+                "archived": true
+            }),
+            serde_json::to_value(&ended).unwrap()
+        );
+        let open_ended_value = serde_json::to_value(&open_ended).unwrap();
+        assert_eq!(Value::Null, open_ended_value["end_date"]);
+        assert_eq!(
+            open_ended,
+            serde_json::from_value(open_ended_value).unwrap()
+        );
+    }
 }
@@ -0,0 +1,122 @@
+use derive_more::{Deref, DerefMut};
+use futures::TryStreamExt;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::{query_as, MySqlPool};
+use std::collections::BTreeMap;
+use tracing::instrument;
+
+/// A mapping of subjects to their identity attributes
+#[derive(Debug, Default, Deref, DerefMut, PartialEq, Eq, Hash, Serialize, JsonSchema)]
+pub struct SubjectIdentities(BTreeMap<String, SubjectIdentity>);
+
+impl SubjectIdentities {
+    /// Fetches [`SubjectIdentities`] from ISPyB
+    #[instrument(name = "fetch_subject_identities")]
+    pub async fn fetch(ispyb_pool: &MySqlPool) -> Result<Self, sqlx::Error> {
+        query_as!(
+            IdentityRow,
+            "
+            SELECT
+                login as subject,
+                LOWER(HEX(externalId)) as external_id,
+                emailAddress as email,
+                familyName as family_name,
+                givenName as given_name
+            FROM Person
+            "
+        )
+        .fetch(ispyb_pool)
+        .try_collect()
+        .await
+    }
+}
+
+/// The identity attributes of a subject, by which they may be known other than their login
+///
+/// ORCIDs are not included, as ISPyB has no column in which to record them
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+pub struct SubjectIdentity {
+    /// The hex encoded identifier of the subject in the user office system
+    pub external_id: Option<String>,
+    /// The email address of the subject
+    pub email: Option<String>,
+    /// The family name of the subject
+    pub family_name: Option<String>,
+    /// The given name of the subject
+    pub given_name: Option<String>,
+}
+
+/// A row from ISPyB detailing the identity attributes of a subject
+struct IdentityRow {
+    /// The unique identifier of the subject
+    subject: Option<String>,
+    /// The hex encoded identifier of the subject in the user office system
+    external_id: Option<String>,
+    /// The email address of the subject
+    email: Option<String>,
+    /// The family name of the subject
+    family_name: Option<String>,
+    /// The given name of the subject
+    given_name: Option<String>,
+}
+
+impl Extend<IdentityRow> for SubjectIdentities {
+    fn extend<T: IntoIterator<Item = IdentityRow>>(&mut self, iter: T) {
+        for identity_row in iter {
+            if let Some(subject) = identity_row.subject {
+                self.insert(
+                    subject,
+                    SubjectIdentity {
+                        external_id: identity_row.external_id,
+                        email: identity_row.email,
+                        family_name: identity_row.family_name,
+                        given_name: identity_row.given_name,
+                    },
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SubjectIdentities, SubjectIdentity};
+    use sqlx::MySqlPool;
+    use std::collections::BTreeMap;
+
+    #[sqlx::test(migrations = "tests/migrations")]
+    async fn fetch_empty(ispyb_pool: MySqlPool) {
+        let identities = SubjectIdentities::fetch(&ispyb_pool).await.unwrap();
+        let expected = SubjectIdentities::default();
+        assert_eq!(expected, identities);
+    }
+
+    #[sqlx::test(
+        migrations = "tests/migrations",
+        fixtures("../../../tests/fixtures/persons.sql")
+    )]
+    async fn fetch_some(ispyb_pool: MySqlPool) {
+        let identities = SubjectIdentities::fetch(&ispyb_pool).await.unwrap();
+        let mut expected = BTreeMap::new();
+        expected.insert(
+            "foo".to_string(),
+            SubjectIdentity {
+                external_id: Some("00000000000000000000000000000014".to_string()),
+                email: Some("foo@example.ac.uk".to_string()),
+                family_name: Some("Foo".to_string()),
+                given_name: Some("Alice".to_string()),
+            },
+        );
+        expected.insert(
+            "bar".to_string(),
+            SubjectIdentity {
+                external_id: None,
+                email: None,
+                family_name: Some("Bar".to_string()),
+                given_name: Some("Bob".to_string()),
+            },
+        );
+        assert_eq!(expected, identities.0);
+    }
+}
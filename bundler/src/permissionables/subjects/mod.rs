@@ -1,3 +1,5 @@
+/// A mapping of subjects to their identity attributes
+mod identities;
 /// A mapping of subjects to their permissions, via roles
 mod permissions;
 /// A mapping of subjects to their proposals
@@ -6,38 +8,65 @@ mod proposals;
 mod sessions;
 
 use self::{
-    permissions::SubjectPermissions, proposals::SubjectProposals, sessions::SubjectSessions,
+    identities::{SubjectIdentities, SubjectIdentity},
+    permissions::SubjectPermissions,
+    proposals::SubjectProposals,
+    sessions::SubjectSessions,
 };
+use super::insert_sorted;
 use derive_more::{Deref, DerefMut};
 use schemars::JsonSchema;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use sqlx::MySqlPool;
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use tokio::try_join;
 use tracing::instrument;
 
 /// A mapping of subjects to their various attributes
-#[derive(Debug, Default, Deref, DerefMut, PartialEq, Eq, Hash, Serialize, JsonSchema)]
+#[derive(
+    Debug, Default, Clone, Deref, DerefMut, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema,
+)]
 pub struct Subjects(BTreeMap<String, Subject>);
 
 /// The various attributes of a subject
-#[derive(Debug, Default, PartialEq, Eq, Hash, Serialize, JsonSchema)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
 pub struct Subject {
-    /// The permissions given to a subject
+    /// The permissions given to a subject, in ascending order
     permissions: Vec<String>,
-    /// The proposals the subject is associated with
+    /// The proposals the subject is associated with, in ascending order
     proposals: Vec<u32>,
-    /// The sessions the subject is associated with
+    /// The sessions the subject is associated with, in ascending order
     sessions: Vec<u32>,
+    /// The roles of the subject on each of their proposals which records any, in ascending order
+    proposal_roles: BTreeMap<u32, BTreeSet<String>>,
+    /// The roles of the subject in each of their sessions which records any, in ascending order
+    session_roles: BTreeMap<u32, BTreeSet<String>>,
+    /// The identity attributes of the subject
+    #[serde(flatten)]
+    identity: SubjectIdentity,
+}
+
+impl Subject {
+    /// The alternate identifiers by which the subject may be known, keyed by their field name and
+    /// normalized to lowercase
+    pub fn aliases(&self) -> impl Iterator<Item = (&'static str, String)> + '_ {
+        [
+            ("external_id", &self.identity.external_id),
+            ("email", &self.identity.email),
+        ]
+        .into_iter()
+        .filter_map(|(field, alias)| Some((field, alias.as_ref()?.to_lowercase())))
+    }
 }
 
 impl Subjects {
     #[instrument(name = "fetch_subjects")]
     pub async fn fetch(ispyb_pool: &MySqlPool) -> Result<Self, sqlx::Error> {
-        let (mut permissions, mut proposals, mut sessions) = try_join!(
+        let (mut permissions, mut proposals, mut sessions, mut identities) = try_join!(
             SubjectPermissions::fetch(ispyb_pool),
             SubjectProposals::fetch(ispyb_pool),
-            SubjectSessions::fetch(ispyb_pool)
+            SubjectSessions::fetch(ispyb_pool),
+            SubjectIdentities::fetch(ispyb_pool)
         )?;
 
         let mut subjects = Self::default();
@@ -48,12 +77,19 @@ impl Subjects {
             .cloned()
             .collect::<HashSet<_>>()
         {
+            let (proposals, proposal_roles) =
+                split_roles(proposals.remove(&subject).unwrap_or_default());
+            let (sessions, session_roles) =
+                split_roles(sessions.remove(&subject).unwrap_or_default());
             subjects.insert(
                 subject.to_owned(),
                 Subject {
                     permissions: permissions.remove(&subject).clone().unwrap_or_default(),
-                    proposals: proposals.remove(&subject).unwrap_or_default(),
-                    sessions: sessions.remove(&subject).unwrap_or_default(),
+                    proposals,
+                    sessions,
+                    proposal_roles,
+                    session_roles,
+                    identity: identities.remove(&subject).unwrap_or_default(),
                 },
             );
         }
@@ -61,3 +97,51 @@ impl Subjects {
         Ok(subjects)
     }
 }
+
+/// Splits memberships into the list of their identifiers, in ascending order and without
+/// duplicates, and a mapping of identifiers to every role held in them, omitting those without
+/// any role
+fn split_roles(
+    memberships: Vec<(u32, Option<String>)>,
+) -> (Vec<u32>, BTreeMap<u32, BTreeSet<String>>) {
+    let mut ids = Vec::new();
+    let mut roles = BTreeMap::<u32, BTreeSet<String>>::new();
+    for (id, role) in memberships {
+        insert_sorted(&mut ids, id);
+        if let Some(role) = role {
+            roles.entry(id).or_default().insert(role);
+        }
+    }
+    (ids, roles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::split_roles;
+    use std::collections::{BTreeMap, BTreeSet};
+
+    #[test]
+    fn split_roles_gathers_every_role() {
+        let memberships = vec![
+            (44, None),
+            (41, Some("Team Leader".to_string())),
+            (44, Some("Principal Investigator".to_string())),
+            (44, Some("Co-Investigator".to_string())),
+            (41, None),
+        ];
+        let expected = (
+            vec![41, 44],
+            BTreeMap::from([
+                (41, BTreeSet::from(["Team Leader".to_string()])),
+                (
+                    44,
+                    BTreeSet::from([
+                        "Co-Investigator".to_string(),
+                        "Principal Investigator".to_string(),
+                    ]),
+                ),
+            ]),
+        );
+        assert_eq!(expected, split_roles(memberships));
+    }
+}
@@ -1,11 +1,13 @@
+use crate::permissionables::insert_sorted;
 use derive_more::{Deref, DerefMut};
+use futures::TryStreamExt;
 use schemars::JsonSchema;
 use serde::Serialize;
 use sqlx::{query_as, MySqlPool};
 use std::collections::BTreeMap;
 use tracing::instrument;
 
-/// A mapping of subjects to their permissions via roles
+/// A mapping of subjects to their permissions via roles, in ascending order
 #[derive(Debug, Default, Deref, DerefMut, PartialEq, Eq, Hash, Serialize, JsonSchema)]
 pub struct SubjectPermissions(BTreeMap<String, Vec<String>>);
 
@@ -13,7 +15,7 @@ impl SubjectPermissions {
     /// Fetches [`SubjectAttributes`] from ISPyB
     #[instrument(name = "fetch_subject_permissions")]
     pub async fn fetch(ispyb_pool: &MySqlPool) -> Result<Self, sqlx::Error> {
-        let permisions_rows = query_as!(
+        query_as!(
             PermissionRow,
             "
             SELECT
@@ -26,10 +28,9 @@ impl SubjectPermissions {
                 JOIN Permission USING (permissionId)
             "
         )
-        .fetch_all(ispyb_pool)
-        .await?;
-
-        Ok(permisions_rows.into_iter().collect())
+        .fetch(ispyb_pool)
+        .try_collect()
+        .await
     }
 }
 
@@ -41,18 +42,13 @@ struct PermissionRow {
     permission: String,
 }
 
-impl FromIterator<PermissionRow> for SubjectPermissions {
-    fn from_iter<T: IntoIterator<Item = PermissionRow>>(iter: T) -> Self {
-        let mut permissions = Self::default();
+impl Extend<PermissionRow> for SubjectPermissions {
+    fn extend<T: IntoIterator<Item = PermissionRow>>(&mut self, iter: T) {
         for permission_row in iter {
             if let Some(fed_id) = permission_row.subject {
-                permissions
-                    .entry(fed_id)
-                    .or_default()
-                    .push(permission_row.permission)
+                insert_sorted(self.entry(fed_id).or_default(), permission_row.permission);
             }
         }
-        permissions
     }
 }
 
@@ -1,24 +1,26 @@
 use derive_more::{Deref, DerefMut};
+use futures::TryStreamExt;
 use schemars::JsonSchema;
 use serde::Serialize;
 use sqlx::{query_as, MySqlPool};
 use std::collections::BTreeMap;
 use tracing::instrument;
 
-/// A mapping of users to their proposals
+/// A mapping of users to their proposals, with their role on each, if any
 #[derive(Debug, Default, Deref, DerefMut, PartialEq, Eq, Hash, Serialize, JsonSchema)]
-pub struct SubjectProposals(BTreeMap<String, Vec<u32>>);
+pub struct SubjectProposals(BTreeMap<String, Vec<(u32, Option<String>)>>);
 
 impl SubjectProposals {
     /// Fetches [`Proposals`] from ISPyB
     #[instrument(name = "fetch_subject_proposals")]
     pub async fn fetch(ispyb_pool: &MySqlPool) -> Result<Self, sqlx::Error> {
-        let proposal_rows = query_as!(
+        query_as!(
             RawProposalRow,
             "
             SELECT
                 login AS subject,
-                proposalNumber AS proposal_number
+                proposalNumber AS proposal_number,
+                role
             FROM ProposalHasPerson
                 INNER JOIN Person USING (personId)
                 INNER JOIN Proposal USING (proposalId)
@@ -26,10 +28,9 @@ impl SubjectProposals {
                 Proposal.externalId IS NOT NULL
             "
         )
-        .fetch_all(ispyb_pool)
-        .await?;
-
-        Ok(proposal_rows.into_iter().collect())
+        .fetch(ispyb_pool)
+        .try_collect()
+        .await
     }
 }
 
@@ -39,12 +40,15 @@ struct ProposalRow {
     subject: String,
     /// The proposal number
     proposal_number: u32,
+    /// The role of the subject on the proposal, if any
+    role: Option<String>,
 }
 
 #[allow(clippy::missing_docs_in_private_items)]
 struct RawProposalRow {
     subject: Option<String>,
     proposal_number: Option<String>,
+    role: Option<String>,
 }
 
 impl TryFrom<RawProposalRow> for ProposalRow {
@@ -57,22 +61,20 @@ impl TryFrom<RawProposalRow> for ProposalRow {
                 .proposal_number
                 .ok_or(anyhow::anyhow!("Proposal number was NULL"))?
                 .parse()?,
+            role: value.role,
         })
     }
 }
 
-impl FromIterator<RawProposalRow> for SubjectProposals {
-    fn from_iter<T: IntoIterator<Item = RawProposalRow>>(iter: T) -> Self {
-        let mut proposals = Self::default();
+impl Extend<RawProposalRow> for SubjectProposals {
+    fn extend<T: IntoIterator<Item = RawProposalRow>>(&mut self, iter: T) {
         for proposal_row in iter {
             if let Ok(proposal_row) = ProposalRow::try_from(proposal_row) {
-                proposals
-                    .entry(proposal_row.subject)
+                self.entry(proposal_row.subject)
                     .or_default()
-                    .push(proposal_row.proposal_number)
+                    .push((proposal_row.proposal_number, proposal_row.role))
             }
         }
-        proposals
     }
 }
 
@@ -100,8 +102,18 @@ mod tests {
     async fn fetch_some(ispyb_pool: MySqlPool) {
         let proposals = SubjectProposals::fetch(&ispyb_pool).await.unwrap();
         let mut expected = BTreeMap::new();
-        expected.insert("foo".to_string(), BTreeSet::from([10030, 10031, 10032]));
-        expected.insert("bar".to_string(), BTreeSet::from([10030]));
+        expected.insert(
+            "foo".to_string(),
+            BTreeSet::from([
+                (10030, Some("Principal Investigator".to_string())),
+                (10031, Some("Co-Investigator".to_string())),
+                (10032, None),
+            ]),
+        );
+        expected.insert(
+            "bar".to_string(),
+            BTreeSet::from([(10030, Some("Co-Investigator".to_string()))]),
+        );
         assert_eq!(
             expected,
             proposals
@@ -1,33 +1,34 @@
 use derive_more::{Deref, DerefMut};
+use futures::TryStreamExt;
 use schemars::JsonSchema;
 use serde::Serialize;
 use sqlx::{query_as, MySqlPool};
 use std::collections::BTreeMap;
 use tracing::instrument;
 
-/// A mapping of subjects to their sessions, possibly via proposals
+/// A mapping of subjects to their sessions, with their role on each, if any
 #[derive(Debug, Default, Deref, DerefMut, PartialEq, Eq, Hash, Serialize, JsonSchema)]
-pub struct SubjectSessions(BTreeMap<String, Vec<u32>>);
+pub struct SubjectSessions(BTreeMap<String, Vec<(u32, Option<String>)>>);
 
 impl SubjectSessions {
     /// Fetches [`Sessions`] from ISPyB
     #[instrument(name = "fetch_subject_sessions")]
     pub async fn fetch(ispyb_pool: &MySqlPool) -> Result<Self, sqlx::Error> {
-        let session_rows = query_as!(
+        query_as!(
             RawSessionRow,
             "
             SELECT
                 login as subject,
-                sessionId as session_id
+                sessionId as session_id,
+                role
             FROM
                 Person
                 INNER JOIN Session_has_Person USING (personId)
             "
         )
-        .fetch_all(ispyb_pool)
-        .await?;
-
-        Ok(session_rows.into_iter().collect())
+        .fetch(ispyb_pool)
+        .try_collect()
+        .await
     }
 }
 
@@ -37,12 +38,15 @@ struct SessionRow {
     subject: String,
     /// An opaque identifier of the session
     session_id: u32,
+    /// The role of the subject in the session, if any
+    role: Option<String>,
 }
 
 #[allow(clippy::missing_docs_in_private_items)]
 struct RawSessionRow {
     subject: Option<String>,
     session_id: u32,
+    role: Option<String>,
 }
 
 impl TryFrom<RawSessionRow> for SessionRow {
@@ -52,22 +56,20 @@ impl TryFrom<RawSessionRow> for SessionRow {
         Ok(Self {
             subject: value.subject.ok_or(anyhow::anyhow!("FedId was NULL"))?,
             session_id: value.session_id,
+            role: value.role,
         })
     }
 }
 
-impl FromIterator<RawSessionRow> for SubjectSessions {
-    fn from_iter<T: IntoIterator<Item = RawSessionRow>>(iter: T) -> Self {
-        let mut sessions = Self::default();
+impl Extend<RawSessionRow> for SubjectSessions {
+    fn extend<T: IntoIterator<Item = RawSessionRow>>(&mut self, iter: T) {
         for session_row in iter {
             if let Ok(session_row) = SessionRow::try_from(session_row) {
-                sessions
-                    .entry(session_row.subject)
+                self.entry(session_row.subject)
                     .or_default()
-                    .push(session_row.session_id);
+                    .push((session_row.session_id, session_row.role));
             }
         }
-        sessions
     }
 }
 
@@ -94,8 +96,14 @@ mod tests {
     async fn fetch_some(ispyb_pool: MySqlPool) {
         let sessions = SubjectSessions::fetch(&ispyb_pool).await.unwrap();
         let mut expected = BTreeMap::new();
-        expected.insert("foo".to_string(), vec![40, 41]);
-        expected.insert("bar".to_string(), vec![43]);
+        expected.insert(
+            "foo".to_string(),
+            vec![(40, Some("Principal Investigator".to_string())), (41, None)],
+        );
+        expected.insert(
+            "bar".to_string(),
+            vec![(43, Some("Local Contact".to_string()))],
+        );
         assert_eq!(expected, sessions.0);
     }
 }
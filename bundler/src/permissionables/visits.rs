@@ -0,0 +1,62 @@
+use super::sessions::Sessions;
+use derive_more::{Deref, DerefMut};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A mapping of canonical visit identifiers, e.g. "cm12345-3", to their sessions
+#[derive(
+    Debug, Default, Deref, DerefMut, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema,
+)]
+pub struct Visits(BTreeMap<String, u32>);
+
+impl From<&Sessions> for Visits {
+    fn from(sessions: &Sessions) -> Self {
+        Self(
+            sessions
+                .iter()
+                .map(|(session_id, session)| (session.visit(), *session_id))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Visits;
+    use crate::permissionables::sessions::Sessions;
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn from_sessions() {
+        let sessions = serde_json::from_value::<Sessions>(json!({
+            "40": {
+                "proposal_code": "cm",
+                "proposal_number": 12345,
+                "visit_number": 3,
+                "beamline": "i22",
+                "start_date": null,
+                "end_date": null,
+                "scheduled": true,
+                "archived": false
+            },
+            "41": {
+                "proposal_code": "mx",
+                "proposal_number": 23456,
+                "visit_number": 1,
+                "beamline": "i03",
+                "start_date": null,
+                "end_date": null,
+                "scheduled": true,
+                "archived": false
+            }
+        }))
+        .unwrap();
+        let expected = Visits(BTreeMap::from([
+            ("cm12345-3".to_string(), 40),
+            ("mx23456-1".to_string(), 41),
+        ]));
+        assert_eq!(expected, Visits::from(&sessions));
+    }
+}
@@ -0,0 +1,194 @@
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    bundle::{BundleOptions, BUNDLE_PREFIX, PERMISSIONABLES},
+    filter::FieldFilters,
+    layout::{BundleLayout, DataPath, LayoutError},
+    static_data::StaticDataFiles,
+    StaticDataGlob,
+};
+
+/// Errors which may occur whilst reading [`BundleProfiles`]
+#[derive(Debug, thiserror::Error)]
+pub enum ProfileError {
+    /// The profiles file could not be read
+    #[error("Error reading bundle profiles {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    /// The profiles file was not valid
+    #[error("Error parsing bundle profiles {0}: {1}")]
+    Parse(PathBuf, serde_json::Error),
+    /// The profile name cannot be used in a URL path
+    #[error("Invalid bundle profile name {0:?}, expected only ASCII letters, digits, '-' or '_'")]
+    InvalidName(String),
+    /// The profile includes a permissionable which does not exist
+    #[error("Bundle profile {0} includes unknown permissionable {1}, expected one of {PERMISSIONABLES:?}")]
+    UnknownPermissionable(String, String),
+    /// The data paths or roots of the profile are invalid or overlap
+    #[error("Bundle profile {0} has an invalid layout: {1}")]
+    Layout(String, LayoutError),
+}
+
+/// A named bundle, served at `/bundles/{name}.tar.gz`, containing a subset of the permissionables
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BundleProfile {
+    /// The permissionables to include in the bundle
+    permissionables: BTreeSet<String>,
+    /// Paths to any static data files that should be included in the bundle - can be globs
+    #[serde(default)]
+    static_data: Vec<StaticDataGlob>,
+    /// The directory relative to which static data files are placed, or none to place them by stem
+    static_data_base: Option<PathBuf>,
+    /// The prefix beneath which data is placed, which is also the root of the bundle
+    #[serde(default = "default_roots_prefix")]
+    roots_prefix: String,
+    /// Paths at which permissionables should be placed instead of beneath the prefix
+    #[serde(default)]
+    data_paths: Vec<DataPath>,
+    /// The bearer token required to fetch the bundle, if it differs from that of the default bundle
+    require_token: Option<String>,
+}
+
+impl BundleProfile {
+    /// The [`BundleOptions`] with which the bundle should be built
+    pub fn options(&self, field_filters: &FieldFilters) -> BundleOptions {
+        BundleOptions {
+            layout: BundleLayout::new(self.roots_prefix.clone(), self.data_paths.clone()),
+            permissionables: self.permissionables.clone(),
+            field_filters: field_filters.clone(),
+        }
+    }
+
+    /// Any static data files that should be included in the bundle
+    pub fn static_data(&self) -> StaticDataFiles {
+        StaticDataFiles::new(self.static_data.clone(), self.static_data_base.clone())
+    }
+
+    /// The bearer token required to fetch the bundle, if it differs from that of the default bundle
+    pub fn require_token(&self) -> Option<&str> {
+        self.require_token.as_deref()
+    }
+}
+
+/// The default roots prefix of a [`BundleProfile`]
+fn default_roots_prefix() -> String {
+    BUNDLE_PREFIX.to_string()
+}
+
+/// A set of [`BundleProfile`]s, keyed by name
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct BundleProfiles(BTreeMap<String, BundleProfile>);
+
+impl BundleProfiles {
+    /// Reads [`BundleProfiles`] from a JSON file, checking that each is valid
+    pub fn read(path: &Path) -> Result<Self, ProfileError> {
+        let contents =
+            std::fs::read(path).map_err(|err| ProfileError::Io(path.to_path_buf(), err))?;
+        let profiles = serde_json::from_slice::<Self>(&contents)
+            .map_err(|err| ProfileError::Parse(path.to_path_buf(), err))?;
+        profiles.validate()?;
+        Ok(profiles)
+    }
+
+    /// Checks that each profile name is URL safe and that each profile includes only known
+    /// permissionables, without overlapping data paths or roots
+    fn validate(&self) -> Result<(), ProfileError> {
+        for (name, profile) in &self.0 {
+            if name.is_empty()
+                || !name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                return Err(ProfileError::InvalidName(name.clone()));
+            }
+            if let Some(permissionable) = profile
+                .permissionables
+                .iter()
+                .find(|permissionable| !PERMISSIONABLES.contains(&permissionable.as_str()))
+            {
+                return Err(ProfileError::UnknownPermissionable(
+                    name.clone(),
+                    permissionable.clone(),
+                ));
+            }
+            profile
+                .options(&FieldFilters::default())
+                .validate()
+                .map_err(|err| ProfileError::Layout(name.clone(), err))?;
+        }
+        Ok(())
+    }
+
+    /// Iterates over each profile and its name
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &BundleProfile)> {
+        self.0.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BundleProfiles, ProfileError};
+    use crate::bundle::BUNDLE_PREFIX;
+    use serde_json::json;
+
+    fn profiles(value: serde_json::Value) -> Result<BundleProfiles, ProfileError> {
+        let profiles = serde_json::from_value::<BundleProfiles>(value).unwrap();
+        profiles.validate()?;
+        Ok(profiles)
+    }
+
+    #[test]
+    fn parse_profiles() {
+        let profiles = profiles(json!({
+            "beamlines": {
+                "permissionables": ["beamlines", "sessions"],
+                "static_data": ["static/*.json"],
+                "require_token": "beamline-token"
+            },
+            "tiled": {
+                "permissionables": ["subjects", "aliases"],
+                "roots_prefix": "tiled/data",
+                "data_paths": ["aliases=tiled/aliases"]
+            }
+        }))
+        .unwrap();
+        let (name, beamlines) = profiles.iter().next().unwrap();
+        assert_eq!("beamlines", name);
+        assert_eq!(Some("beamline-token"), beamlines.require_token());
+        let options = beamlines.options(&Default::default());
+        assert_eq!(
+            vec![BUNDLE_PREFIX],
+            options.layout.roots(&options.permissionables)
+        );
+        assert_eq!(
+            vec!["beamlines", "sessions"],
+            options.permissionables.iter().collect::<Vec<_>>()
+        );
+        let (_, tiled) = profiles.iter().nth(1).unwrap();
+        let options = tiled.options(&Default::default());
+        assert_eq!(
+            vec!["tiled/data", "tiled/aliases"],
+            options.layout.roots(&options.permissionables)
+        );
+    }
+
+    #[test]
+    fn reject_invalid_profiles() {
+        assert!(matches!(
+            profiles(json!({"a/b": {"permissionables": []}})),
+            Err(ProfileError::InvalidName(_))
+        ));
+        assert!(matches!(
+            profiles(json!({"admin": {"permissionables": ["admin"]}})),
+            Err(ProfileError::UnknownPermissionable(_, _))
+        ));
+        assert!(matches!(
+            profiles(json!({"admin": {"permissionables": [], "roots_prefix": "/data"}})),
+            Err(ProfileError::Layout(_, _))
+        ));
+    }
+}
@@ -0,0 +1,162 @@
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+use tokio::sync::watch;
+
+use crate::static_data::StaticDataFiles;
+
+/// A watcher of the directories containing static data files, which stops watching when dropped
+pub struct StaticDataWatcher {
+    /// The underlying watcher of the directories
+    _watcher: RecommendedWatcher,
+    /// Whether every directory is being watched, and no errors have been reported whilst watching
+    complete: Arc<AtomicBool>,
+}
+
+impl StaticDataWatcher {
+    /// Whether every change to static data is certain to be noticed, such that it need not also
+    /// be reloaded when polling
+    ///
+    /// This is not the case if any directory was missing when the watch began, or if any error
+    /// has since been reported, after which events may have been missed
+    pub fn is_complete(&self) -> bool {
+        self.complete.load(Ordering::Relaxed)
+    }
+}
+
+/// Watches the directories containing static data files, marking the returned receiver as changed
+/// whenever anything within them changes
+///
+/// Directories are watched, rather than the matched files, such that files created after the
+/// watch began and atomically swapped ConfigMap mounts are both noticed
+pub fn watch_static_data<'a>(
+    static_data: impl IntoIterator<Item = &'a StaticDataFiles>,
+) -> Result<(StaticDataWatcher, watch::Receiver<()>), notify::Error> {
+    let (sender, changed) = watch::channel(());
+    let complete = Arc::new(AtomicBool::new(true));
+    let mut watcher = notify::recommended_watcher({
+        let complete = complete.clone();
+        move |event: notify::Result<Event>| match event {
+            Ok(event) if !matches!(event.kind, EventKind::Access(_)) => sender.send_replace(()),
+            Ok(_) => {}
+            Err(err) => {
+                tracing::warn!("Error watching static data, will also reload when polling: {err}");
+                complete.store(false, Ordering::Relaxed);
+                sender.send_replace(());
+            }
+        }
+    })?;
+    for static_data in static_data {
+        for pattern in static_data.patterns() {
+            let (directory, recursive_mode) = watched_directory(Path::new(pattern.as_ref()));
+            if !directory.is_dir() {
+                tracing::warn!(
+                    "Not watching static data in missing directory {directory:?}, will reload when polling"
+                );
+                complete.store(false, Ordering::Relaxed);
+                continue;
+            }
+            tracing::info!("Watching static data in {directory:?}");
+            watcher.watch(&directory, recursive_mode)?;
+        }
+    }
+    Ok((
+        StaticDataWatcher {
+            _watcher: watcher,
+            complete,
+        },
+        changed,
+    ))
+}
+
+/// The deepest directory containing every file which may match a glob pattern, and whether it
+/// must be watched recursively to see them all
+fn watched_directory(pattern: &Path) -> (PathBuf, RecursiveMode) {
+    let components = pattern.components().collect::<Vec<_>>();
+    let literal = components
+        .iter()
+        .take_while(|component| !is_wildcard(component))
+        .count();
+    let (directory, remaining) = if literal == components.len() {
+        (
+            pattern.parent().map(Path::to_path_buf).unwrap_or_default(),
+            1,
+        )
+    } else {
+        (
+            components[..literal].iter().collect::<PathBuf>(),
+            components.len() - literal,
+        )
+    };
+    let directory = if directory.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        directory
+    };
+    let recursive_mode = if remaining > 1 {
+        RecursiveMode::Recursive
+    } else {
+        RecursiveMode::NonRecursive
+    };
+    (directory, recursive_mode)
+}
+
+/// Whether a path component contains any glob wildcards
+fn is_wildcard(component: &Component) -> bool {
+    component
+        .as_os_str()
+        .to_string_lossy()
+        .contains(['*', '?', '['])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{watch_static_data, watched_directory};
+    use crate::static_data::StaticDataFiles;
+    use notify::RecursiveMode;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn directory_of_pattern() {
+        assert_eq!(
+            (PathBuf::from("/srv/bundler"), RecursiveMode::NonRecursive),
+            watched_directory(Path::new("/srv/bundler/*.json"))
+        );
+        assert_eq!(
+            (PathBuf::from("static"), RecursiveMode::Recursive),
+            watched_directory(Path::new("static/**/*.yaml"))
+        );
+        assert_eq!(
+            (PathBuf::from("static"), RecursiveMode::NonRecursive),
+            watched_directory(Path::new("static/admin.json"))
+        );
+        assert_eq!(
+            (PathBuf::from("."), RecursiveMode::NonRecursive),
+            watched_directory(Path::new("*.json"))
+        );
+    }
+
+    #[test]
+    fn incomplete_with_missing_directory() {
+        let dir = std::env::temp_dir().join(format!("bundler-reload-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let present = StaticDataFiles::new(
+            vec![format!("{}/*.json", dir.display()).parse().unwrap()],
+            None,
+        );
+        let missing = StaticDataFiles::new(
+            vec![format!("{}/missing/*.json", dir.display()).parse().unwrap()],
+            None,
+        );
+        let (watcher, _) = watch_static_data([&present]).unwrap();
+        assert!(watcher.is_complete());
+        let (watcher, _) = watch_static_data([&present, &missing]).unwrap();
+        assert!(!watcher.is_complete());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
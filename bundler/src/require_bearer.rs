@@ -1,3 +1,4 @@
+use crate::metrics::METRICS;
 use axum::{
     extract::Request,
     http::StatusCode,
@@ -79,6 +80,7 @@ where
             if valid_token {
                 Ok(future.await?)
             } else {
+                METRICS.record_unauthorized();
                 Ok(StatusCode::UNAUTHORIZED.into_response())
             }
         })
@@ -0,0 +1,200 @@
+use crate::permissionables::Part;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::Add,
+    str::FromStr,
+    time::Duration,
+};
+use tokio::time::Instant;
+
+/// Errors which may occur whilst parsing a [`RefreshInterval`]
+#[derive(Debug, thiserror::Error)]
+pub enum ScheduleError {
+    /// The refresh interval was not of the form `<permissionable>=<interval>`
+    #[error("Expected a refresh interval of the form <permissionable>=<interval>, found {0}")]
+    Malformed(String),
+    /// The refresh interval names a permissionable which is not fetched separately
+    #[error(
+        "Cannot refresh {0} separately, expected one of subjects, sessions, proposals or beamlines"
+    )]
+    UnknownPermissionable(String),
+    /// The interval could not be parsed as a duration
+    #[error("Invalid refresh interval {0}: {1}")]
+    InvalidInterval(String, humantime::DurationError),
+    /// The interval was zero, such that the permissionable would be refreshed continuously
+    #[error("The refresh interval of {0} must not be zero")]
+    ZeroInterval(String),
+}
+
+/// The interval at which a part of the permissionables is refreshed, written as
+/// `<permissionable>=<interval>`, e.g. `subjects=10m`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefreshInterval {
+    /// The part of the permissionables to refresh
+    part: Part,
+    /// The interval at which it is refreshed
+    interval: Duration,
+}
+
+impl FromStr for RefreshInterval {
+    type Err = ScheduleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, interval) = s
+            .split_once('=')
+            .ok_or_else(|| ScheduleError::Malformed(s.to_string()))?;
+        let part = Part::from_name(name)
+            .ok_or_else(|| ScheduleError::UnknownPermissionable(name.to_string()))?;
+        let interval = interval
+            .parse::<humantime::Duration>()
+            .map_err(|err| ScheduleError::InvalidInterval(interval.to_string(), err))?;
+        if interval.is_zero() {
+            return Err(ScheduleError::ZeroInterval(name.to_string()));
+        }
+        Ok(Self {
+            part,
+            interval: *interval,
+        })
+    }
+}
+
+/// When each part of the permissionables is next due to be refreshed
+#[derive(Debug)]
+pub struct RefreshSchedule {
+    /// The interval at which each part is refreshed
+    intervals: BTreeMap<Part, Duration>,
+    /// When each part is next due to be refreshed
+    next_refresh: BTreeMap<Part, Instant>,
+}
+
+impl RefreshSchedule {
+    /// Creates a [`RefreshSchedule`] which refreshes each part at the polling interval, unless it
+    /// is given its own, beginning one interval from now
+    pub fn new(polling_interval: Duration, refresh_intervals: &[RefreshInterval]) -> Self {
+        let mut intervals = Part::ALL
+            .into_iter()
+            .map(|part| (part, polling_interval))
+            .collect::<BTreeMap<_, _>>();
+        for refresh_interval in refresh_intervals {
+            intervals.insert(refresh_interval.part, refresh_interval.interval);
+        }
+        let now = Instant::now();
+        let next_refresh = intervals
+            .iter()
+            .map(|(part, interval)| (*part, now.add(*interval)))
+            .collect();
+        Self {
+            intervals,
+            next_refresh,
+        }
+    }
+
+    /// When the next refresh of any part is due
+    pub fn next_refresh(&self) -> Instant {
+        self.next_refresh.values().copied().min().unwrap()
+    }
+
+    /// The parts which are due to be refreshed by the given instant
+    pub fn due(&self, now: Instant) -> BTreeSet<Part> {
+        self.next_refresh
+            .iter()
+            .filter(|(_, next_refresh)| **next_refresh <= now)
+            .map(|(part, _)| *part)
+            .collect()
+    }
+
+    /// The shortest interval at which any of the parts is refreshed
+    pub fn shortest_interval(&self, parts: &BTreeSet<Part>) -> Duration {
+        parts
+            .iter()
+            .map(|part| self.intervals[part])
+            .min()
+            .unwrap_or(Duration::MAX)
+    }
+
+    /// The longest interval at which any part is refreshed
+    pub fn longest_interval(&self) -> Duration {
+        self.intervals.values().copied().max().unwrap()
+    }
+
+    /// Schedules the next refresh of each of the parts one interval after it was due
+    pub fn refreshed(&mut self, parts: &BTreeSet<Part>) {
+        for part in parts {
+            let next_refresh = self.next_refresh.get_mut(part).unwrap();
+            *next_refresh = next_refresh.add(self.intervals[part]);
+        }
+    }
+
+    /// Schedules the next refresh of every part one interval from now, as all have just been
+    /// refreshed on demand
+    pub fn refreshed_all(&mut self) {
+        let now = Instant::now();
+        for (part, next_refresh) in &mut self.next_refresh {
+            *next_refresh = now.add(self.intervals[part]);
+        }
+    }
+
+    /// Schedules a retry of each of the parts after the delay
+    pub fn retry(&mut self, parts: &BTreeSet<Part>, retry_delay: Duration) {
+        let retry_at = Instant::now().add(retry_delay);
+        for part in parts {
+            self.next_refresh.insert(*part, retry_at);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RefreshInterval, RefreshSchedule, ScheduleError};
+    use crate::permissionables::Part;
+    use std::{collections::BTreeSet, ops::Add, time::Duration};
+
+    #[test]
+    fn parse_refresh_interval() {
+        assert_eq!(
+            RefreshInterval {
+                part: Part::Subjects,
+                interval: Duration::from_secs(600)
+            },
+            "subjects=10m".parse().unwrap()
+        );
+        assert!(matches!(
+            "subjects".parse::<RefreshInterval>(),
+            Err(ScheduleError::Malformed(_))
+        ));
+        assert!(matches!(
+            "aliases=10m".parse::<RefreshInterval>(),
+            Err(ScheduleError::UnknownPermissionable(_))
+        ));
+        assert!(matches!(
+            "subjects=often".parse::<RefreshInterval>(),
+            Err(ScheduleError::InvalidInterval(_, _))
+        ));
+        assert!(matches!(
+            "subjects=0s".parse::<RefreshInterval>(),
+            Err(ScheduleError::ZeroInterval(_))
+        ));
+    }
+
+    #[test]
+    fn parts_due_at_their_intervals() {
+        let mut schedule =
+            RefreshSchedule::new(Duration::from_secs(60), &["subjects=10m".parse().unwrap()]);
+        let first = schedule.next_refresh();
+        assert_eq!(
+            BTreeSet::from([Part::Sessions, Part::Proposals, Part::Beamlines]),
+            schedule.due(first)
+        );
+        schedule.refreshed(&schedule.due(first));
+        assert_eq!(first.add(Duration::from_secs(60)), schedule.next_refresh());
+        assert_eq!(
+            BTreeSet::from(Part::ALL),
+            schedule.due(first.add(Duration::from_secs(540)))
+        );
+        assert_eq!(
+            Duration::from_secs(60),
+            schedule.shortest_interval(&BTreeSet::from(Part::ALL))
+        );
+        assert_eq!(Duration::from_secs(600), schedule.longest_interval());
+    }
+}
@@ -0,0 +1,414 @@
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use serde_json::{ser::Formatter, value::RawValue};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+/// The name of the file in which bundle signatures are stored
+pub const SIGNATURES_FILE: &str = ".signatures.json";
+
+/// The name of the hashing algorithm used for file digests, as understood by Open Policy Agent
+const HASH_ALGORITHM: &str = "SHA-256";
+
+/// The algorithms with which a bundle may be signed
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+#[clap(rename_all = "UPPER")]
+pub enum SigningAlgorithm {
+    /// HMAC using SHA-256, with a shared secret
+    HS256,
+    /// RSASSA-PKCS1-v1_5 using SHA-256, with a PEM encoded RSA key
+    RS256,
+    /// ECDSA using P-256 and SHA-256, with a PEM encoded EC key
+    ES256,
+}
+
+impl From<SigningAlgorithm> for Algorithm {
+    fn from(value: SigningAlgorithm) -> Self {
+        match value {
+            SigningAlgorithm::HS256 => Algorithm::HS256,
+            SigningAlgorithm::RS256 => Algorithm::RS256,
+            SigningAlgorithm::ES256 => Algorithm::ES256,
+        }
+    }
+}
+
+/// Errors which may occur whilst signing or verifying a bundle
+#[derive(Debug, thiserror::Error)]
+pub enum SigningError {
+    /// The key file could not be read
+    #[error("Could not read key file: {0}")]
+    Io(#[from] std::io::Error),
+    /// The key could not be parsed, or the signature could not be produced or decoded
+    #[error("Invalid signature or key: {0}")]
+    Jwt(#[from] jsonwebtoken::errors::Error),
+    /// A file could not be hashed as it was not valid JSON
+    #[error("Could not hash JSON file {0}: {1}")]
+    Json(String, serde_json::Error),
+    /// The bundle did not contain a signatures file
+    #[error("Bundle does not contain {SIGNATURES_FILE}")]
+    Unsigned,
+    /// The bundle contained a number of signatures other than one
+    #[error("Expected exactly one signature, found {0}")]
+    SignatureCount(usize),
+    /// A file in the bundle was not covered by the signature
+    #[error("File {0} is not signed")]
+    UnsignedFile(String),
+    /// A file covered by the signature was not in the bundle
+    #[error("Signed file {0} is missing from the bundle")]
+    MissingFile(String),
+    /// A file in the bundle did not match the digest in the signature
+    #[error("Digest of file {0} does not match signature")]
+    DigestMismatch(String),
+}
+
+/// The contents of the signatures file
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Signatures {
+    /// A set of JSON Web Tokens, each containing [`SignedFiles`]
+    signatures: Vec<String>,
+}
+
+/// The claims of a bundle signature
+#[derive(Debug, Serialize, Deserialize)]
+struct SignedFiles {
+    /// The digest of each file in the bundle
+    files: Vec<FileDigest>,
+    /// The identifier of the key used to produce the signature
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keyid: Option<String>,
+}
+
+/// The digest of a single file in the bundle
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileDigest {
+    /// The path of the file within the bundle
+    name: String,
+    /// The hex encoded digest of the file
+    hash: String,
+    /// The algorithm used to produce the digest
+    algorithm: String,
+}
+
+/// A private key with which bundles are signed
+pub struct BundleSigner {
+    /// The algorithm with which signatures are produced
+    algorithm: SigningAlgorithm,
+    /// The key with which signatures are produced
+    key: EncodingKey,
+    /// The identifier of the key, as configured in Open Policy Agent
+    key_id: Option<String>,
+}
+
+impl std::fmt::Debug for BundleSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BundleSigner")
+            .field("algorithm", &self.algorithm)
+            .field("key_id", &self.key_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl FileDigest {
+    /// Computes the [`FileDigest`] of a file, given its path within the bundle
+    pub fn new(name: &str, data: &[u8]) -> Result<Self, SigningError> {
+        Ok(Self {
+            name: name.to_string(),
+            hash: digest(name, data)?,
+            algorithm: HASH_ALGORITHM.to_string(),
+        })
+    }
+}
+
+impl BundleSigner {
+    /// Creates a [`BundleSigner`] from the shared secret for [`SigningAlgorithm::HS256`] or a PEM
+    /// encoded private key otherwise
+    pub fn new(
+        algorithm: SigningAlgorithm,
+        key: &[u8],
+        key_id: Option<String>,
+    ) -> Result<Self, SigningError> {
+        let key = match algorithm {
+            SigningAlgorithm::HS256 => EncodingKey::from_secret(key.trim_ascii_end()),
+            SigningAlgorithm::RS256 => EncodingKey::from_rsa_pem(key)?,
+            SigningAlgorithm::ES256 => EncodingKey::from_ec_pem(key)?,
+        };
+        Ok(Self {
+            algorithm,
+            key,
+            key_id,
+        })
+    }
+
+    /// Reads the key of a [`BundleSigner`] from a file
+    pub fn from_file(
+        algorithm: SigningAlgorithm,
+        path: impl AsRef<Path>,
+        key_id: Option<String>,
+    ) -> Result<Self, SigningError> {
+        Self::new(algorithm, &std::fs::read(path)?, key_id)
+    }
+
+    /// Produces the [`Signatures`] of a set of files, keyed by their path within the bundle
+    pub fn sign<'a>(
+        &self,
+        files: impl IntoIterator<Item = (&'a str, &'a [u8])>,
+    ) -> Result<Signatures, SigningError> {
+        let files = files
+            .into_iter()
+            .map(|(name, data)| FileDigest::new(name, data))
+            .collect::<Result<_, SigningError>>()?;
+        self.sign_digests(files)
+    }
+
+    /// Produces the [`Signatures`] of a set of files from their [`FileDigest`]s, such that the
+    /// files need not all be held in memory at once
+    pub fn sign_digests(&self, files: Vec<FileDigest>) -> Result<Signatures, SigningError> {
+        let mut header = Header::new(self.algorithm.into());
+        header.kid.clone_from(&self.key_id);
+        let claims = SignedFiles {
+            files,
+            keyid: self.key_id.clone(),
+        };
+        Ok(Signatures {
+            signatures: vec![jsonwebtoken::encode(&header, &claims, &self.key)?],
+        })
+    }
+}
+
+/// Verifies the signature of a bundle, given the files it contains keyed by their path, using the
+/// shared secret for [`SigningAlgorithm::HS256`] or a PEM encoded public key otherwise
+pub fn verify(
+    files: &BTreeMap<String, Vec<u8>>,
+    algorithm: SigningAlgorithm,
+    key: &[u8],
+) -> Result<(), SigningError> {
+    let key = match algorithm {
+        SigningAlgorithm::HS256 => DecodingKey::from_secret(key.trim_ascii_end()),
+        SigningAlgorithm::RS256 => DecodingKey::from_rsa_pem(key)?,
+        SigningAlgorithm::ES256 => DecodingKey::from_ec_pem(key)?,
+    };
+    let signatures = files.get(SIGNATURES_FILE).ok_or(SigningError::Unsigned)?;
+    let signatures = serde_json::from_slice::<Signatures>(signatures)
+        .map_err(|err| SigningError::Json(SIGNATURES_FILE.to_string(), err))?;
+    let [signature] = signatures.signatures.as_slice() else {
+        return Err(SigningError::SignatureCount(signatures.signatures.len()));
+    };
+    let mut validation = Validation::new(algorithm.into());
+    validation.required_spec_claims.clear();
+    validation.validate_exp = false;
+    let mut signed_files = jsonwebtoken::decode::<SignedFiles>(signature, &key, &validation)?
+        .claims
+        .files
+        .into_iter()
+        .map(|file| (file.name, file.hash))
+        .collect::<BTreeMap<_, _>>();
+    for (name, data) in files.iter().filter(|(name, _)| *name != SIGNATURES_FILE) {
+        let hash = signed_files
+            .remove(name)
+            .ok_or_else(|| SigningError::UnsignedFile(name.clone()))?;
+        if hash != digest(name, data)? {
+            return Err(SigningError::DigestMismatch(name.clone()));
+        }
+    }
+    match signed_files.into_keys().next() {
+        Some(name) => Err(SigningError::MissingFile(name)),
+        None => Ok(()),
+    }
+}
+
+/// Computes the hex encoded SHA-256 digest of a file in the bundle
+///
+/// As in Open Policy Agent, JSON files are hashed in a canonical form, with sorted keys and
+/// without whitespace, whilst all other files are hashed as is
+fn digest(name: &str, data: &[u8]) -> Result<String, SigningError> {
+    let hash = if name.ends_with(".json") || name.ends_with(".manifest") {
+        let mut hasher = Sha256::new();
+        write_canonical(&mut hasher, data)
+            .map_err(|err| SigningError::Json(name.to_string(), err))?;
+        hasher.finalize()
+    } else {
+        Sha256::digest(data)
+    };
+    Ok(hex::encode(hash))
+}
+
+/// Writes a JSON document in canonical form, with sorted keys and without whitespace, through a
+/// buffer
+///
+/// The form matches that in which Open Policy Agent hashes JSON files, which is encoded by Go's
+/// `encoding/json`. As such numbers are written exactly as they appear in the document, and line
+/// and paragraph separators in strings are escaped. Nested values are canonicalized one at a time,
+/// such that large data files are never parsed whole
+fn write_canonical(writer: impl Write, data: &[u8]) -> Result<(), serde_json::Error> {
+    let mut writer = BufWriter::new(writer);
+    write_canonical_value(&mut writer, serde_json::from_slice(data)?)?;
+    writer.flush().map_err(serde_json::Error::io)
+}
+
+/// Writes a single JSON value in canonical form, recursing into objects and arrays
+fn write_canonical_value(
+    writer: &mut impl Write,
+    value: &RawValue,
+) -> Result<(), serde_json::Error> {
+    let json = value.get();
+    match json.as_bytes().first() {
+        Some(b'{') => {
+            let entries = serde_json::from_str::<BTreeMap<String, &RawValue>>(json)?;
+            writer.write_all(b"{").map_err(serde_json::Error::io)?;
+            for (index, (key, value)) in entries.into_iter().enumerate() {
+                if index > 0 {
+                    writer.write_all(b",").map_err(serde_json::Error::io)?;
+                }
+                write_canonical_string(writer, &key)?;
+                writer.write_all(b":").map_err(serde_json::Error::io)?;
+                write_canonical_value(writer, value)?;
+            }
+            writer.write_all(b"}").map_err(serde_json::Error::io)
+        }
+        Some(b'[') => {
+            let elements = serde_json::from_str::<Vec<&RawValue>>(json)?;
+            writer.write_all(b"[").map_err(serde_json::Error::io)?;
+            for (index, element) in elements.into_iter().enumerate() {
+                if index > 0 {
+                    writer.write_all(b",").map_err(serde_json::Error::io)?;
+                }
+                write_canonical_value(writer, element)?;
+            }
+            writer.write_all(b"]").map_err(serde_json::Error::io)
+        }
+        Some(b'"') => write_canonical_string(writer, &serde_json::from_str::<String>(json)?),
+        _ => writer
+            .write_all(json.as_bytes())
+            .map_err(serde_json::Error::io),
+    }
+}
+
+/// Writes a JSON string in canonical form, escaped as by Go's `encoding/json`
+fn write_canonical_string(writer: &mut impl Write, string: &str) -> Result<(), serde_json::Error> {
+    string.serialize(&mut serde_json::Serializer::with_formatter(
+        writer,
+        GoFormatter,
+    ))
+}
+
+/// A [`Formatter`] which escapes strings as Go's `encoding/json` does, without HTML escaping
+///
+/// This differs from the compact formatter only in escaping line and paragraph separators, which
+/// Go escapes as they are not valid in JavaScript strings
+struct GoFormatter;
+
+impl Formatter for GoFormatter {
+    fn write_string_fragment<W: ?Sized + Write>(
+        &mut self,
+        writer: &mut W,
+        fragment: &str,
+    ) -> std::io::Result<()> {
+        let mut start = 0;
+        for (index, separator) in fragment.match_indices(['\u{2028}', '\u{2029}']) {
+            writer.write_all(&fragment.as_bytes()[start..index])?;
+            writer.write_all(match separator {
+                "\u{2028}" => b"\\u2028",
+                _ => b"\\u2029",
+            })?;
+            start = index + separator.len();
+        }
+        writer.write_all(&fragment.as_bytes()[start..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        verify, write_canonical, BundleSigner, SigningAlgorithm, SigningError, SIGNATURES_FILE,
+    };
+    use std::collections::BTreeMap;
+
+    fn signed_files(secret: &str) -> BTreeMap<String, Vec<u8>> {
+        let signer = BundleSigner::new(SigningAlgorithm::HS256, secret.as_bytes(), None).unwrap();
+        let mut files = BTreeMap::from([
+            (".manifest".to_string(), br#"{"revision":"1"}"#.to_vec()),
+            (
+                "diamond/data/subjects/data.json".to_string(),
+                br#"{"foo": {"sessions": [40]}}"#.to_vec(),
+            ),
+        ]);
+        let signatures = signer
+            .sign(
+                files
+                    .iter()
+                    .map(|(name, data)| (name.as_str(), data.as_slice())),
+            )
+            .unwrap();
+        files.insert(
+            SIGNATURES_FILE.to_string(),
+            serde_json::to_vec(&signatures).unwrap(),
+        );
+        files
+    }
+
+    #[test]
+    fn canonical_form() {
+        for (data, canonical) in [
+            (
+                r#"{"9": {"b": 1, "a": [2]}, "10": null}"#,
+                r#"{"10":null,"9":{"a":[2],"b":1}}"#,
+            ),
+            (r#"[{"b": 1, "a": 2}]"#, r#"[{"a":2,"b":1}]"#),
+            ("{}", "{}"),
+            (
+                r#"{"b": [1.50, 1e3, -0.0, 1E+2], "a": 1.0}"#,
+                r#"{"a":1.0,"b":[1.50,1e3,-0.0,1E+2]}"#,
+            ),
+            (
+                "{\"\u{2028}\": \"a\u{2029}b\", \"c\": \"<\\n\\u0001\\u2028>\"}",
+                r#"{"c":"<\n\u0001\u2028>","\u2028":"a\u2029b"}"#,
+            ),
+        ] {
+            let mut written = Vec::new();
+            write_canonical(&mut written, data.as_bytes()).unwrap();
+            assert_eq!(canonical, String::from_utf8(written).unwrap());
+        }
+    }
+
+    #[test]
+    fn verify_signed() {
+        let files = signed_files("secret\n");
+        verify(&files, SigningAlgorithm::HS256, b"secret").unwrap();
+    }
+
+    #[test]
+    fn verify_wrong_key() {
+        let files = signed_files("secret");
+        assert!(matches!(
+            verify(&files, SigningAlgorithm::HS256, b"other"),
+            Err(SigningError::Jwt(_))
+        ));
+    }
+
+    #[test]
+    fn verify_tampered() {
+        let mut files = signed_files("secret");
+        files.insert(
+            "diamond/data/subjects/data.json".to_string(),
+            br#"{"foo": {"sessions": [40, 41]}}"#.to_vec(),
+        );
+        assert!(matches!(
+            verify(&files, SigningAlgorithm::HS256, b"secret"),
+            Err(SigningError::DigestMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn verify_unsigned_file() {
+        let mut files = signed_files("secret");
+        files.insert("diamond/data/admin/data.json".to_string(), b"{}".to_vec());
+        assert!(matches!(
+            verify(&files, SigningAlgorithm::HS256, b"secret"),
+            Err(SigningError::UnsignedFile(_))
+        ));
+    }
+}
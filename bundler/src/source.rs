@@ -0,0 +1,234 @@
+use crate::{
+    bundle::BundleDataError,
+    metrics::METRICS,
+    permissionables::{checksums::TableChecksums, Part, Permissionables},
+};
+use sqlx::{query_scalar, raw_sql, MySqlPool};
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use tracing::instrument;
+
+/// A source from which [`Permissionables`] can be fetched
+#[derive(Debug)]
+pub enum PermissionablesSource {
+    /// An ISPyB database, either live or loaded from a SQL dump
+    Ispyb(MySqlPool),
+    /// A JSON file containing a snapshot of the [`Permissionables`]
+    Snapshot(PathBuf),
+}
+
+/// A cheaply computed summary of a [`PermissionablesSource`], which differs whenever the
+/// [`Permissionables`] it would supply may have changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SourceFingerprint {
+    /// Checksums of the ISPyB tables from which the permissionables are fetched
+    Ispyb(TableChecksums),
+    /// When the snapshot was last modified
+    Snapshot(SystemTime),
+}
+
+impl SourceFingerprint {
+    /// The fingerprint of only that which the part of the [`Permissionables`] is fetched from,
+    /// such that changes which cannot affect it are disregarded
+    pub fn of_part(&self, part: Part) -> Self {
+        match self {
+            Self::Ispyb(checksums) => Self::Ispyb(checksums.of_tables(part.tables())),
+            Self::Snapshot(modified) => Self::Snapshot(*modified),
+        }
+    }
+}
+
+impl PermissionablesSource {
+    /// Loads a SQL dump, such as a `mysqldump` of ISPyB or a test fixture, into the database and
+    /// fetches [`Permissionables`] from it as if it were ISPyB
+    ///
+    /// The statements of the dump are executed as is, so the dump is only loaded into a database
+    /// which contains no tables, such that a live ISPyB cannot be modified. The dump must therefore
+    /// create the tables as well as populate them
+    #[instrument(name = "load_sql_dump")]
+    pub async fn from_sql_dump(
+        ispyb_pool: MySqlPool,
+        path: &Path,
+    ) -> Result<Self, BundleDataError> {
+        let sql_dump = tokio::fs::read_to_string(path)
+            .await
+            .map_err(|err| BundleDataError::SqlDump(path.to_path_buf(), err))?;
+        let table_count = query_scalar!(
+            "
+            SELECT
+                COUNT(*) AS `table_count!`
+            FROM
+                information_schema.TABLES
+            WHERE
+                TABLE_SCHEMA = DATABASE()
+            "
+        )
+        .fetch_one(&ispyb_pool)
+        .await?;
+        if table_count > 0 {
+            return Err(BundleDataError::NonEmptyDatabase(path.to_path_buf()));
+        }
+        raw_sql(&sql_dump).execute(&ispyb_pool).await?;
+        Ok(Self::Ispyb(ispyb_pool))
+    }
+
+    /// Fetches the current [`Permissionables`] from the source
+    #[instrument(name = "fetch_source")]
+    pub async fn fetch(&self) -> Result<Permissionables, BundleDataError> {
+        match self {
+            Self::Ispyb(ispyb_pool) => Ok(Permissionables::fetch(ispyb_pool).await?),
+            Self::Snapshot(path) => {
+                let snapshot = tokio::fs::read(path)
+                    .await
+                    .map_err(|err| BundleDataError::Snapshot(path.clone(), err))?;
+                serde_json::from_slice(&snapshot)
+                    .map_err(|err| BundleDataError::Snapshot(path.clone(), err.into()))
+            }
+        }
+    }
+
+    /// Fetches the given parts of the [`Permissionables`] from the source, replacing those held
+    #[instrument(name = "fetch_source_parts", skip(permissionables))]
+    pub async fn fetch_parts(
+        &self,
+        parts: &BTreeSet<Part>,
+        permissionables: &mut Permissionables,
+    ) -> Result<(), BundleDataError> {
+        match self {
+            Self::Ispyb(ispyb_pool) => Ok(permissionables.fetch_parts(ispyb_pool, parts).await?),
+            Self::Snapshot(_) => {
+                permissionables.replace_parts(self.fetch().await?, parts);
+                Ok(())
+            }
+        }
+    }
+
+    /// Computes the current [`SourceFingerprint`], such that changes can be detected without
+    /// fetching the [`Permissionables`]
+    #[instrument(name = "fingerprint_source")]
+    pub async fn fingerprint(&self) -> Result<SourceFingerprint, BundleDataError> {
+        match self {
+            Self::Ispyb(ispyb_pool) => Ok(SourceFingerprint::Ispyb(
+                METRICS
+                    .time_fetch("checksums", TableChecksums::fetch(ispyb_pool))
+                    .await?,
+            )),
+            Self::Snapshot(path) => {
+                let modified = tokio::fs::metadata(path)
+                    .await
+                    .and_then(|metadata| metadata.modified())
+                    .map_err(|err| BundleDataError::Snapshot(path.clone(), err))?;
+                Ok(SourceFingerprint::Snapshot(modified))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PermissionablesSource;
+    use crate::{
+        bundle::BundleDataError,
+        permissionables::{beamlines::Beamlines, Permissionables},
+    };
+    use sqlx::MySqlPool;
+    use std::path::Path;
+
+    #[tokio::test]
+    async fn fetch_snapshot() {
+        let source = PermissionablesSource::Snapshot("tests/snapshots/permissionables.json".into());
+        let permissionables = source.fetch().await.unwrap();
+        let expected = serde_json::from_value::<Permissionables>(serde_json::json!({
+            "subjects": {
+                "foo": {
+                    "permissions": ["read_data"],
+                    "proposals": [10030],
+                    "sessions": [40],
+                    "proposal_roles": {"10030": ["Principal Investigator"]},
+                    "session_roles": {"40": ["Principal Investigator"]}
+                }
+            },
+            "sessions": {
+                "40": {
+                    "proposal_code": "cm",
+                    "proposal_number": 10030,
+                    "visit_number": 10,
+                    "beamline": "i12",
+                    "start_date": "2023-01-10T09:00:00Z",
+                    "end_date": "2023-01-12T09:00:00Z",
+                    "scheduled": true,
+                    "archived": true
+                }
+            },
+            "proposals": {
+                "10030": {
+                    "proposal_code": "cm",
+                    "title": "Tomography of battery electrodes",
+                    "state": "Open",
+                    "sessions": {"10": 40}
+                }
+            },
+            "beamlines": {"i12": {"sessions": [40]}}
+        }))
+        .unwrap();
+        assert_eq!(expected, permissionables);
+    }
+
+    #[tokio::test]
+    async fn fingerprint_snapshot() {
+        let source = PermissionablesSource::Snapshot("tests/snapshots/permissionables.json".into());
+        assert_eq!(
+            source.fingerprint().await.unwrap(),
+            source.fingerprint().await.unwrap()
+        );
+        let missing = PermissionablesSource::Snapshot("tests/snapshots/missing.json".into());
+        assert!(missing.fingerprint().await.is_err());
+    }
+
+    #[sqlx::test]
+    async fn fetch_sql_dump(ispyb_pool: MySqlPool) {
+        let source = PermissionablesSource::from_sql_dump(
+            ispyb_pool.clone(),
+            Path::new("tests/dumps/beamline_sessions.sql"),
+        )
+        .await
+        .unwrap();
+        let permissionables = source.fetch().await.unwrap();
+        assert_eq!(
+            Beamlines::fetch(&ispyb_pool).await.unwrap(),
+            *permissionables.beamlines
+        );
+        assert_eq!(5, permissionables.beamlines.len());
+    }
+
+    #[sqlx::test(migrations = "tests/migrations")]
+    async fn refuse_sql_dump_into_non_empty_database(ispyb_pool: MySqlPool) {
+        assert!(matches!(
+            PermissionablesSource::from_sql_dump(
+                ispyb_pool,
+                Path::new("tests/fixtures/beamline_sessions.sql")
+            )
+            .await,
+            Err(BundleDataError::NonEmptyDatabase(_))
+        ));
+    }
+
+    #[sqlx::test]
+    async fn load_missing_sql_dump(ispyb_pool: MySqlPool) {
+        assert!(PermissionablesSource::from_sql_dump(
+            ispyb_pool,
+            Path::new("tests/fixtures/missing.sql")
+        )
+        .await
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn fetch_missing_snapshot() {
+        let source = PermissionablesSource::Snapshot("tests/snapshots/missing.json".into());
+        assert!(source.fetch().await.is_err());
+    }
+}
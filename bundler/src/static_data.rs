@@ -0,0 +1,327 @@
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
+use tracing::trace;
+
+use crate::StaticDataGlob;
+
+/// Errors which may occur whilst reading static data files
+#[derive(Debug, thiserror::Error)]
+pub enum StaticDataError {
+    /// The file could not be read
+    #[error("Could not read {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    /// The file was not valid JSON
+    #[error("Invalid JSON in {0}: {1}")]
+    Json(PathBuf, serde_json::Error),
+    /// The file was not valid YAML, or could not be represented as JSON
+    #[error("Invalid YAML in {0}: {1}")]
+    Yaml(PathBuf, serde_yaml::Error),
+    /// The file was not valid TOML
+    #[error("Invalid TOML in {0}: {1}")]
+    Toml(PathBuf, toml::de::Error),
+    /// The file extension does not correspond to a supported format
+    #[error("Unsupported static data format of {0}, expected a .json, .yaml, .yml or .toml file")]
+    UnsupportedFormat(PathBuf),
+    /// The JSON Schema alongside the file was not a valid schema
+    #[error("Invalid JSON Schema {0}: {1}")]
+    InvalidSchema(PathBuf, String),
+    /// The file did not match the JSON Schema alongside it
+    #[error("{0} does not match its schema: {mismatches}", mismatches = .1.join("; "))]
+    SchemaMismatch(PathBuf, Vec<String>),
+    /// The file is not within the base directory of the static data
+    #[error("Static data file {0} is not within the base directory {1}")]
+    OutsideBase(PathBuf, PathBuf),
+    /// Two different files would be placed at the same path within the bundle
+    #[error("Static data files {1} and {2} would both be placed at {0}")]
+    Collision(String, PathBuf, PathBuf),
+}
+
+/// The suffix of JSON Schema files, each of which describes the static data file sharing its stem
+const SCHEMA_SUFFIX: &str = ".schema.json";
+
+/// Static data files to be included in a bundle, matched by glob patterns and keyed by their path
+/// relative to a base directory, or by their stem if no base is given
+#[derive(Debug, Clone, Default)]
+pub struct StaticDataFiles {
+    /// Paths to any static data files - can be globs
+    patterns: Vec<StaticDataGlob>,
+    /// The directory relative to which files are keyed, such that subdirectories map to nested
+    /// data paths
+    base: Option<PathBuf>,
+}
+
+impl StaticDataFiles {
+    /// Creates [`StaticDataFiles`] from glob patterns and an optional base directory
+    pub fn new(patterns: Vec<StaticDataGlob>, base: Option<PathBuf>) -> Self {
+        Self { patterns, base }
+    }
+
+    /// The glob patterns matching the static data files
+    pub fn patterns(&self) -> &[StaticDataGlob] {
+        &self.patterns
+    }
+
+    /// Read static data from files that should be included in the compiled bundle, parsing each
+    /// according to its extension, checking it against any JSON Schema alongside it and
+    /// re-serializing it as JSON
+    pub async fn read(&self) -> Result<BTreeMap<String, Vec<u8>>, StaticDataError> {
+        let mut data = BTreeMap::new();
+        for (name, file) in self.files()? {
+            let contents = tokio::fs::read(&file)
+                .await
+                .map_err(|err| StaticDataError::Io(file.clone(), err))?;
+            let value = parse(&file, &contents)?;
+            if let Some((schema_file, schema)) = read_schema(&file)? {
+                validate(&file, &schema_file, &schema, &value)?;
+            }
+            data.insert(
+                name,
+                serde_json::to_vec(&value).map_err(|err| StaticDataError::Json(file, err))?,
+            );
+        }
+        Ok(data)
+    }
+
+    /// Reads the JSON Schema of each static data file which has one alongside it, keyed by name
+    pub fn schemas(&self) -> Result<BTreeMap<String, Value>, StaticDataError> {
+        let mut schemas = BTreeMap::new();
+        for (name, file) in self.files()? {
+            if let Some((schema_file, schema)) = read_schema(&file)? {
+                jsonschema::validator_for(&schema)
+                    .map_err(|err| StaticDataError::InvalidSchema(schema_file, err.to_string()))?;
+                schemas.insert(name, schema);
+            }
+        }
+        Ok(schemas)
+    }
+
+    /// Finds the static data files matching the glob patterns, keyed by name, skipping any JSON
+    /// Schemas and rejecting different files with the same name
+    fn files(&self) -> Result<BTreeMap<String, PathBuf>, StaticDataError> {
+        let mut files = BTreeMap::<String, PathBuf>::new();
+        for pattern in &self.patterns {
+            for file in glob::glob(pattern.as_ref()).expect("Pattern was validated by CLI") {
+                let file =
+                    file.map_err(|e| StaticDataError::Io(e.path().to_path_buf(), e.into_error()))?;
+                if is_schema(&file) {
+                    continue;
+                }
+                trace!(glob = pattern.as_ref(), file = ?file, "Reading static data from {file:?}");
+                let Some(name) = self.name(&file)? else {
+                    // Save having to think about non-utf8 in OPA rules
+                    trace!("Skipping non-utf8 static file: {file:?}");
+                    continue;
+                };
+                match files.get(&name) {
+                    Some(existing) if *existing != file => {
+                        return Err(StaticDataError::Collision(name, existing.clone(), file))
+                    }
+                    Some(_) => {}
+                    None => {
+                        files.insert(name, file);
+                    }
+                }
+            }
+        }
+        Ok(files)
+    }
+
+    /// The name of a static data file: its path relative to the base directory without its
+    /// extension, or its stem if there is no base, or [`None`] if it is not valid UTF-8
+    fn name(&self, file: &Path) -> Result<Option<String>, StaticDataError> {
+        let Some(base) = &self.base else {
+            return Ok(file.file_stem().and_then(OsStr::to_str).map(str::to_string));
+        };
+        let relative = file
+            .strip_prefix(base)
+            .map_err(|_| StaticDataError::OutsideBase(file.to_path_buf(), base.clone()))?
+            .with_extension("");
+        Ok(relative
+            .components()
+            .map(|component| component.as_os_str().to_str())
+            .collect::<Option<Vec<_>>>()
+            .map(|components| components.join("/")))
+    }
+}
+
+/// Whether the file is the JSON Schema of another static data file
+fn is_schema(file: &Path) -> bool {
+    file.file_name()
+        .and_then(OsStr::to_str)
+        .is_some_and(|name| name.ends_with(SCHEMA_SUFFIX))
+}
+
+/// Reads the JSON Schema alongside a static data file, if there is one
+fn read_schema(file: &Path) -> Result<Option<(PathBuf, Value)>, StaticDataError> {
+    let Some(stem) = file.file_stem().and_then(OsStr::to_str) else {
+        return Ok(None);
+    };
+    let schema_file = file.with_file_name(format!("{stem}{SCHEMA_SUFFIX}"));
+    let contents = match std::fs::read(&schema_file) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(StaticDataError::Io(schema_file, err)),
+    };
+    let schema = serde_json::from_slice(&contents)
+        .map_err(|err| StaticDataError::Json(schema_file.clone(), err))?;
+    Ok(Some((schema_file, schema)))
+}
+
+/// Checks parsed static data against its JSON Schema, reporting every mismatch
+fn validate(
+    file: &Path,
+    schema_file: &Path,
+    schema: &Value,
+    value: &Value,
+) -> Result<(), StaticDataError> {
+    let validator = jsonschema::validator_for(schema).map_err(|err| {
+        StaticDataError::InvalidSchema(schema_file.to_path_buf(), err.to_string())
+    })?;
+    let mismatches = validator
+        .iter_errors(value)
+        .map(|err| format!("{err} at \"{}\"", err.instance_path))
+        .collect::<Vec<_>>();
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(StaticDataError::SchemaMismatch(
+            file.to_path_buf(),
+            mismatches,
+        ))
+    }
+}
+
+/// Parses the contents of a static data file according to its extension
+fn parse(file: &Path, contents: &[u8]) -> Result<Value, StaticDataError> {
+    match file.extension().and_then(OsStr::to_str) {
+        Some("json") => serde_json::from_slice(contents)
+            .map_err(|err| StaticDataError::Json(file.to_path_buf(), err)),
+        Some("yaml" | "yml") => serde_yaml::from_slice(contents)
+            .map_err(|err| StaticDataError::Yaml(file.to_path_buf(), err)),
+        Some("toml") => std::str::from_utf8(contents)
+            .map_err(|err| {
+                StaticDataError::Io(
+                    file.to_path_buf(),
+                    std::io::Error::new(std::io::ErrorKind::InvalidData, err),
+                )
+            })
+            .and_then(|contents| {
+                toml::from_str(contents)
+                    .map_err(|err| StaticDataError::Toml(file.to_path_buf(), err))
+            }),
+        _ => Err(StaticDataError::UnsupportedFormat(file.to_path_buf())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, validate, StaticDataError, StaticDataFiles};
+    use serde_json::json;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn parse_formats() {
+        let expected = json!({"i22_admin": ["i22", "b21"]});
+        assert_eq!(
+            expected,
+            parse(Path::new("admin.json"), br#"{"i22_admin": ["i22", "b21"]}"#).unwrap()
+        );
+        assert_eq!(
+            expected,
+            parse(Path::new("admin.yaml"), b"i22_admin:\n  - i22\n  - b21\n").unwrap()
+        );
+        assert_eq!(
+            expected,
+            parse(Path::new("admin.toml"), br#"i22_admin = ["i22", "b21"]"#).unwrap()
+        );
+    }
+
+    #[test]
+    fn reject_invalid() {
+        assert!(matches!(
+            parse(Path::new("admin.json"), b"{\"i22_admin\": [\"i22\""),
+            Err(StaticDataError::Json(path, _)) if path == Path::new("admin.json")
+        ));
+        assert!(matches!(
+            parse(Path::new("admin.yml"), b"i22_admin: [i22"),
+            Err(StaticDataError::Yaml(_, _))
+        ));
+        assert!(matches!(
+            parse(Path::new("admin.toml"), b"i22_admin = "),
+            Err(StaticDataError::Toml(_, _))
+        ));
+        assert!(matches!(
+            parse(Path::new("admin.txt"), b"{}"),
+            Err(StaticDataError::UnsupportedFormat(_))
+        ));
+    }
+
+    #[test]
+    fn validate_against_schema() {
+        let schema = json!({
+            "type": "object",
+            "additionalProperties": {"type": "array", "items": {"type": "string"}}
+        });
+        let file = Path::new("admin.json");
+        let schema_file = Path::new("admin.schema.json");
+        validate(file, schema_file, &schema, &json!({"i22_admin": ["i22"]})).unwrap();
+        let Err(StaticDataError::SchemaMismatch(path, mismatches)) = validate(
+            file,
+            schema_file,
+            &schema,
+            &json!({"i22_admin": ["i22", 22], "b21_admin": "b21"}),
+        ) else {
+            panic!("Expected a schema mismatch");
+        };
+        assert_eq!(file, path);
+        assert_eq!(2, mismatches.len());
+        assert!(matches!(
+            validate(file, schema_file, &json!({"type": 7}), &json!({})),
+            Err(StaticDataError::InvalidSchema(_, _))
+        ));
+    }
+
+    #[test]
+    fn name_relative_to_base() {
+        let files = StaticDataFiles::new(vec![], Some(PathBuf::from("static")));
+        assert_eq!(
+            Some("facility/admin".to_string()),
+            files.name(Path::new("static/facility/admin.yaml")).unwrap()
+        );
+        assert!(matches!(
+            files.name(Path::new("other/admin.json")),
+            Err(StaticDataError::OutsideBase(_, _))
+        ));
+        assert_eq!(
+            Some("admin".to_string()),
+            StaticDataFiles::default()
+                .name(Path::new("static/facility/admin.json"))
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn reject_collisions() {
+        let dir = std::env::temp_dir().join(format!("bundler-static-{}", std::process::id()));
+        for facility in ["a", "b"] {
+            std::fs::create_dir_all(dir.join(facility)).unwrap();
+            std::fs::write(dir.join(facility).join("admin.json"), "{}").unwrap();
+        }
+        let pattern = format!("{}/*/admin.json", dir.display()).parse().unwrap();
+        let nested = StaticDataFiles::new(vec![pattern], Some(dir.clone()))
+            .files()
+            .unwrap();
+        assert_eq!(
+            vec!["a/admin", "b/admin"],
+            nested.keys().collect::<Vec<_>>()
+        );
+        let pattern = format!("{}/*/admin.json", dir.display()).parse().unwrap();
+        let flat = StaticDataFiles::new(vec![pattern], None).files();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(flat, Err(StaticDataError::Collision(name, _, _)) if name == "admin"));
+    }
+}
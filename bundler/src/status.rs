@@ -0,0 +1,359 @@
+use crate::bundle::Bundle;
+use serde::Serialize;
+use std::fmt::Debug;
+use std::{
+    collections::BTreeMap,
+    time::{Duration, SystemTime},
+};
+
+/// Details of a successfully built bundle
+#[derive(Debug, Clone)]
+pub struct BundleSummary {
+    /// The revision of the bundle
+    pub revision: String,
+    /// When the bundle was built
+    pub built_at: SystemTime,
+    /// The number of entries in each of the permissionables
+    pub entry_counts: BTreeMap<String, usize>,
+}
+
+impl BundleSummary {
+    /// Summarises a [`Bundle`] which has just been built
+    pub fn new<Metadata: Debug + Serialize>(bundle: &Bundle<Metadata>) -> Self {
+        Self {
+            revision: bundle.revision().to_string(),
+            built_at: SystemTime::now(),
+            entry_counts: bundle.entry_counts(),
+        }
+    }
+}
+
+/// The outcome of recent attempts to update a single bundle
+#[derive(Debug)]
+struct BundleStatus {
+    /// The bundle produced by the most recent successful update
+    bundle: BundleSummary,
+    /// When the permissionables in the bundle were last fetched, or found to be unchanged
+    refreshed_at: SystemTime,
+    /// The error which caused the most recent update to fail, if it did
+    error: Option<String>,
+}
+
+impl BundleStatus {
+    /// The time since the bundle being served was last successfully refreshed
+    fn bundle_age(&self) -> Duration {
+        self.refreshed_at.elapsed().unwrap_or_default()
+    }
+}
+
+/// The outcome of recent attempts to refresh the bundles
+#[derive(Debug)]
+pub struct RefreshStatus {
+    /// The outcome of recent updates of each bundle, keyed by the path at which it is served
+    bundles: BTreeMap<String, BundleStatus>,
+    /// The time taken by the most recent successful fetch of the permissionables
+    fetch_duration: Duration,
+    /// When the permissionables were last successfully fetched, or found to be unchanged
+    fetched_at: SystemTime,
+    /// The error which caused the most recent refresh to fail, if it did
+    last_error: Option<String>,
+    /// The number of refreshes which have failed since the last success
+    consecutive_failures: u32,
+}
+
+/// The health of the service, as reported by the health endpoint
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Health {
+    /// The most recent refresh and the most recent update of every bundle succeeded
+    Ok,
+    /// The most recent refresh or update of a bundle failed, a previous bundle is being served
+    Degraded,
+}
+
+/// A summary of the [`BundleStatus`] of a single bundle for reporting to clients
+#[derive(Debug, Serialize)]
+pub struct BundleHealthReport {
+    /// The revision of the bundle being served
+    revision: String,
+    /// The time in seconds since the bundle being served was last successfully refreshed
+    bundle_age_seconds: u64,
+    /// The error which caused the most recent update of the bundle to fail, if it did
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// A summary of the [`RefreshStatus`] for reporting to clients
+#[derive(Debug, Serialize)]
+pub struct HealthReport {
+    /// The health of the service
+    status: Health,
+    /// The error which caused the most recent refresh to fail, if it did
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_error: Option<String>,
+    /// The number of refreshes which have failed since the last success
+    consecutive_failures: u32,
+    /// The health of each bundle, keyed by the path at which it is served
+    bundles: BTreeMap<String, BundleHealthReport>,
+}
+
+/// A detailed report of the [`BundleStatus`] of a single bundle
+#[derive(Debug, Serialize)]
+pub struct BundleStatusReport {
+    /// The health of the bundle
+    #[serde(flatten)]
+    health: BundleHealthReport,
+    /// When the bundle being served was built, in RFC 3339 format
+    built_at: String,
+    /// The number of entries in each of the permissionables of the bundle being served
+    entry_counts: BTreeMap<String, usize>,
+}
+
+/// A detailed report of the [`RefreshStatus`] and the bundles being served
+#[derive(Debug, Serialize)]
+pub struct StatusReport {
+    /// The health of the service
+    status: Health,
+    /// The error which caused the most recent refresh to fail, if it did
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_error: Option<String>,
+    /// The number of refreshes which have failed since the last success
+    consecutive_failures: u32,
+    /// The time in seconds taken by the most recent successful fetch of the permissionables
+    fetch_duration_seconds: f64,
+    /// The status of each bundle, keyed by the path at which it is served
+    bundles: BTreeMap<String, BundleStatusReport>,
+}
+
+impl RefreshStatus {
+    /// Creates a [`RefreshStatus`] for bundles, keyed by the path at which they are served, which
+    /// have just been built from permissionables fetched in the given time
+    pub fn new(bundles: BTreeMap<String, BundleSummary>, fetch_duration: Duration) -> Self {
+        Self {
+            bundles: bundles
+                .into_iter()
+                .map(|(path, bundle)| {
+                    (
+                        path,
+                        BundleStatus {
+                            refreshed_at: bundle.built_at,
+                            bundle,
+                            error: None,
+                        },
+                    )
+                })
+                .collect(),
+            fetch_duration,
+            fetched_at: SystemTime::now(),
+            last_error: None,
+            consecutive_failures: 0,
+        }
+    }
+
+    /// Records a successful fetch of the permissionables, or a check which found them unchanged
+    pub fn fetched(&mut self, fetch_duration: Duration) {
+        self.fetch_duration = fetch_duration;
+        self.fetched_at = SystemTime::now();
+    }
+
+    /// Records a successful refresh
+    pub fn succeeded(&mut self) {
+        self.last_error = None;
+        self.consecutive_failures = 0;
+    }
+
+    /// Records a failed refresh, returning the number of consecutive failures
+    pub fn failed(&mut self, error: &anyhow::Error) -> u32 {
+        self.last_error = Some(error.to_string());
+        self.consecutive_failures += 1;
+        self.consecutive_failures
+    }
+
+    /// Records that the bundle served at the path has been rebuilt from the most recently fetched
+    /// permissionables
+    pub fn bundle_updated(&mut self, path: &str, bundle: BundleSummary) {
+        self.bundles.insert(
+            path.to_string(),
+            BundleStatus {
+                bundle,
+                refreshed_at: self.fetched_at,
+                error: None,
+            },
+        );
+    }
+
+    /// Records that every bundle is up to date with the most recently fetched permissionables,
+    /// which were unchanged and so did not require the bundles to be rebuilt
+    pub fn bundles_unchanged(&mut self) {
+        for status in self.bundles.values_mut() {
+            status.refreshed_at = self.fetched_at;
+        }
+    }
+
+    /// Records a failed update of the bundle served at the path, which continues to be served
+    pub fn bundle_failed(&mut self, path: &str, error: &anyhow::Error) {
+        if let Some(status) = self.bundles.get_mut(path) {
+            status.error = Some(error.to_string());
+        }
+    }
+
+    /// Whether any bundle failed to update when last attempted
+    pub fn any_bundle_failed(&self) -> bool {
+        self.bundles.values().any(|status| status.error.is_some())
+    }
+
+    /// Whether every bundle being served is no older than the maximum age
+    pub fn is_ready(&self, max_bundle_age: Duration) -> bool {
+        self.bundles
+            .values()
+            .all(|status| status.bundle_age() <= max_bundle_age)
+    }
+
+    /// The health of the service
+    fn health_status(&self) -> Health {
+        if self.last_error.is_some() || self.any_bundle_failed() {
+            Health::Degraded
+        } else {
+            Health::Ok
+        }
+    }
+
+    /// Summarises the [`RefreshStatus`] as a [`HealthReport`]
+    pub fn health(&self) -> HealthReport {
+        HealthReport {
+            status: self.health_status(),
+            last_error: self.last_error.clone(),
+            consecutive_failures: self.consecutive_failures,
+            bundles: self
+                .bundles
+                .iter()
+                .map(|(path, status)| (path.clone(), bundle_health(status)))
+                .collect(),
+        }
+    }
+
+    /// Details the [`RefreshStatus`] as a [`StatusReport`]
+    pub fn status(&self) -> StatusReport {
+        StatusReport {
+            status: self.health_status(),
+            last_error: self.last_error.clone(),
+            consecutive_failures: self.consecutive_failures,
+            fetch_duration_seconds: self.fetch_duration.as_secs_f64(),
+            bundles: self
+                .bundles
+                .iter()
+                .map(|(path, status)| {
+                    (
+                        path.clone(),
+                        BundleStatusReport {
+                            health: bundle_health(status),
+                            built_at: humantime::format_rfc3339_seconds(status.bundle.built_at)
+                                .to_string(),
+                            entry_counts: status.bundle.entry_counts.clone(),
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Summarises the [`BundleStatus`] of a single bundle as a [`BundleHealthReport`]
+fn bundle_health(status: &BundleStatus) -> BundleHealthReport {
+    BundleHealthReport {
+        revision: status.bundle.revision.clone(),
+        bundle_age_seconds: status.bundle_age().as_secs(),
+        error: status.error.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BundleSummary, Health, RefreshStatus};
+    use std::{
+        collections::BTreeMap,
+        time::{Duration, SystemTime},
+    };
+
+    const DEFAULT: &str = "/bundle.tar.gz";
+    const PROFILE: &str = "/bundles/beamlines.tar.gz";
+
+    fn summary(revision: &str, age: Duration) -> BundleSummary {
+        BundleSummary {
+            revision: revision.to_string(),
+            built_at: SystemTime::now() - age,
+            entry_counts: BTreeMap::from([("subjects".to_string(), 2)]),
+        }
+    }
+
+    fn status(default_age: Duration, profile_age: Duration) -> RefreshStatus {
+        RefreshStatus::new(
+            BTreeMap::from([
+                (DEFAULT.to_string(), summary("abc", default_age)),
+                (PROFILE.to_string(), summary("def", profile_age)),
+            ]),
+            Duration::from_millis(1500),
+        )
+    }
+
+    #[test]
+    fn degraded_until_success() {
+        let mut status = status(Duration::ZERO, Duration::ZERO);
+        assert_eq!(Health::Ok, status.health().status);
+        assert_eq!(1, status.failed(&anyhow::anyhow!("Connection refused")));
+        assert_eq!(2, status.failed(&anyhow::anyhow!("Connection refused")));
+        let report = status.health();
+        assert_eq!(Health::Degraded, report.status);
+        assert_eq!(Some("Connection refused".to_string()), report.last_error);
+        status.succeeded();
+        assert_eq!(Health::Ok, status.health().status);
+        assert_eq!(0, status.health().consecutive_failures);
+    }
+
+    #[test]
+    fn bundle_errors_recorded_separately() {
+        let mut status = status(Duration::ZERO, Duration::ZERO);
+        status.bundle_failed(PROFILE, &anyhow::anyhow!("Invalid JSON"));
+        status.bundle_updated(DEFAULT, summary("ghi", Duration::ZERO));
+        let report = status.health();
+        assert_eq!(Health::Degraded, report.status);
+        assert!(status.any_bundle_failed());
+        assert_eq!(None, report.bundles[DEFAULT].error);
+        assert_eq!("ghi", report.bundles[DEFAULT].revision);
+        assert_eq!(
+            Some("Invalid JSON".to_string()),
+            report.bundles[PROFILE].error
+        );
+        assert_eq!("def", report.bundles[PROFILE].revision);
+        status.bundle_updated(PROFILE, summary("jkl", Duration::ZERO));
+        let report = status.health();
+        assert_eq!(Health::Ok, report.status);
+        assert_eq!("jkl", report.bundles[PROFILE].revision);
+    }
+
+    #[test]
+    fn ready_until_any_stale() {
+        let max_bundle_age = Duration::from_secs(180);
+        assert!(status(Duration::from_secs(60), Duration::from_secs(60)).is_ready(max_bundle_age));
+        assert!(!status(Duration::from_secs(60), Duration::from_secs(240)).is_ready(max_bundle_age));
+        let mut status = status(Duration::from_secs(240), Duration::from_secs(240));
+        assert!(!status.is_ready(max_bundle_age));
+        status.fetched(Duration::from_secs(1));
+        status.bundle_updated(DEFAULT, summary("ghi", Duration::ZERO));
+        status.bundle_failed(PROFILE, &anyhow::anyhow!("Invalid JSON"));
+        assert!(!status.is_ready(max_bundle_age));
+        status.bundles_unchanged();
+        assert!(status.is_ready(max_bundle_age));
+    }
+
+    #[test]
+    fn status_report() {
+        let report =
+            serde_json::to_value(status(Duration::ZERO, Duration::from_secs(60)).status()).unwrap();
+        assert_eq!("ok", report["status"]);
+        assert_eq!(1.5, report["fetch_duration_seconds"]);
+        assert_eq!("abc", report["bundles"][DEFAULT]["revision"]);
+        assert_eq!(2, report["bundles"][DEFAULT]["entry_counts"]["subjects"]);
+        assert_eq!(60, report["bundles"][PROFILE]["bundle_age_seconds"]);
+    }
+}
@@ -0,0 +1,168 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::Serialize;
+use std::time::Duration;
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::{mpsc, oneshot},
+};
+
+/// The period over which triggers are gathered, such that those arriving close together are
+/// satisfied by a single refresh
+pub const TRIGGER_COALESCE_PERIOD: Duration = Duration::from_millis(500);
+
+/// Errors which may occur whilst waiting for a triggered refresh
+#[derive(Debug, thiserror::Error)]
+pub enum TriggerError {
+    /// The bundles are no longer being updated
+    #[error("The bundle updater is not running")]
+    Stopped,
+    /// The refresh was attempted but failed, the previous bundle continues to be served
+    #[error("Failed to refresh bundles: {0}")]
+    Failed(String),
+}
+
+/// A trigger awaiting the revision of the default bundle, or the error which prevented a refresh
+type Responder = oneshot::Sender<Result<String, String>>;
+
+/// A handle with which an immediate refresh of the bundles can be triggered
+#[derive(Debug, Clone)]
+pub struct RefreshTrigger(mpsc::UnboundedSender<Responder>);
+
+impl RefreshTrigger {
+    /// Creates a [`RefreshTrigger`] and the [`RefreshTriggers`] on which its triggers are received
+    pub fn new() -> (Self, RefreshTriggers) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        (Self(sender), RefreshTriggers(receiver))
+    }
+
+    /// Triggers a refresh, returning the revision of the default bundle once it is being served
+    pub async fn trigger(&self) -> Result<String, TriggerError> {
+        let (responder, response) = oneshot::channel();
+        self.0.send(responder).map_err(|_| TriggerError::Stopped)?;
+        response
+            .await
+            .map_err(|_| TriggerError::Stopped)?
+            .map_err(TriggerError::Failed)
+    }
+}
+
+/// The receiving end of a [`RefreshTrigger`]
+pub struct RefreshTriggers(mpsc::UnboundedReceiver<Responder>);
+
+impl RefreshTriggers {
+    /// Waits for a trigger, returning none once every [`RefreshTrigger`] has been dropped
+    ///
+    /// This is cancel safe, such that no trigger is lost if another branch of a
+    /// [`tokio::select!`] completes first
+    pub async fn recv(&mut self) -> Option<PendingRefresh> {
+        self.0
+            .recv()
+            .await
+            .map(|responder| PendingRefresh(vec![responder]))
+    }
+
+    /// Adds any triggers which have arrived since the pending refresh was received
+    pub fn gather(&mut self, pending: &mut PendingRefresh) {
+        while let Ok(responder) = self.0.try_recv() {
+            pending.0.push(responder);
+        }
+    }
+}
+
+/// Triggers which will all be satisfied by the same refresh
+pub struct PendingRefresh(Vec<Responder>);
+
+impl PendingRefresh {
+    /// The number of triggers awaiting the refresh
+    pub fn count(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Responds to every trigger with the outcome of the refresh
+    pub fn respond(self, outcome: Result<&str, &anyhow::Error>) {
+        for responder in self.0 {
+            // The trigger may have stopped waiting, e.g. if the client disconnected
+            let _ = responder.send(outcome.map(str::to_string).map_err(|err| err.to_string()));
+        }
+    }
+}
+
+/// The revision being served after a triggered refresh
+#[derive(Debug, Serialize)]
+struct RefreshReport {
+    /// The revision of the default bundle
+    revision: String,
+}
+
+/// Triggers an immediate refresh of the bundles, returning the revision of the default bundle once
+/// it is being served
+pub async fn refresh_endpoint(State(trigger): State<RefreshTrigger>) -> impl IntoResponse {
+    match trigger.trigger().await {
+        Ok(revision) => Ok(Json(RefreshReport { revision })),
+        Err(err @ TriggerError::Stopped) => Err((StatusCode::SERVICE_UNAVAILABLE, err.to_string())),
+        Err(err @ TriggerError::Failed(_)) => {
+            Err((StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))
+        }
+    }
+}
+
+/// Triggers an immediate refresh of the bundles whenever SIGHUP is received
+///
+/// If SIGHUP cannot be listened for, the error is logged and refreshes may only be triggered by
+/// request
+pub async fn refresh_on_hangup(trigger: RefreshTrigger) {
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(err) => {
+            tracing::error!(
+                "Unable to listen for SIGHUP, refreshes will not be triggered by it: {err}"
+            );
+            return;
+        }
+    };
+    while hangups.recv().await.is_some() {
+        tracing::info!("Received SIGHUP, refreshing bundles");
+        match trigger.trigger().await {
+            Ok(revision) => tracing::info!("Refreshed bundles to revision {revision}"),
+            Err(err) => tracing::error!("{err}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RefreshTrigger, TriggerError};
+
+    #[tokio::test]
+    async fn triggers_gathered_into_one_refresh() {
+        let (trigger, mut triggers) = RefreshTrigger::new();
+        let first = tokio::spawn({
+            let trigger = trigger.clone();
+            async move { trigger.trigger().await }
+        });
+        let mut pending = triggers.recv().await.unwrap();
+        let second = tokio::spawn({
+            let trigger = trigger.clone();
+            async move { trigger.trigger().await }
+        });
+        while pending.count() < 2 {
+            tokio::task::yield_now().await;
+            triggers.gather(&mut pending);
+        }
+        pending.respond(Ok("abc"));
+        assert_eq!("abc", first.await.unwrap().unwrap());
+        assert_eq!("abc", second.await.unwrap().unwrap());
+    }
+
+    #[tokio::test]
+    async fn failed_refresh_reported() {
+        let (trigger, mut triggers) = RefreshTrigger::new();
+        let waiting = tokio::spawn(async move { trigger.trigger().await });
+        let pending = triggers.recv().await.unwrap();
+        pending.respond(Err(&anyhow::anyhow!("Connection refused")));
+        assert!(matches!(
+            waiting.await.unwrap(),
+            Err(TriggerError::Failed(err)) if err == "Connection refused"
+        ));
+    }
+}
@@ -0,0 +1,32 @@
+CREATE TABLE ProposalHasPerson LIKE ispyb_build.ProposalHasPerson;
+
+CREATE TABLE BLSession LIKE ispyb_build.BLSession;
+
+CREATE TABLE Session_has_Person LIKE ispyb_build.Session_has_Person;
+
+CREATE TABLE Person LIKE ispyb_build.Person;
+
+CREATE TABLE UserGroup LIKE ispyb_build.UserGroup;
+
+CREATE TABLE
+    UserGroup_has_Person LIKE ispyb_build.UserGroup_has_Person;
+
+CREATE TABLE Permission LIKE ispyb_build.Permission;
+
+CREATE TABLE
+    UserGroup_has_Permission LIKE ispyb_build.UserGroup_has_Permission;
+
+CREATE TABLE Proposal LIKE ispyb_build.Proposal;
+
+INSERT INTO
+    `BLSession` (
+        `sessionId`,
+        `proposalId`,
+        `visit_number`,
+        `beamLineName`,
+        `startDate`,
+        `endDate`,
+        `scheduled`,
+        `archived`
+    )
+VALUES (40, 30, 10, "i12", "2023-01-10 09:00:00", "2023-01-12 09:00:00", 1, 1), (41, 30, 11, "i22", "2024-03-01 09:00:00", NULL, 1, 0), (42, 30, 12, "b13", "2024-05-01 09:00:00", "2024-05-03 09:00:00", 0, 0), (43, 31, 10, "p99", NULL, NULL, NULL, 0), (44, 31, 11, "i22", "2023-06-01 09:00:00", "2023-06-02 09:00:00", 1, 0), (45, 32, 1, "b21", NULL, NULL, 0, 0);
@@ -1,3 +1,10 @@
 INSERT INTO
-    `Person` (`personId`, `login`)
-VALUES (20, "foo"), (21, "bar");
\ No newline at end of file
+    `Person` (
+        `personId`,
+        `login`,
+        `externalId`,
+        `emailAddress`,
+        `familyName`,
+        `givenName`
+    )
+VALUES (20, "foo", UNHEX("00000000000000000000000000000014"), "foo@example.ac.uk", "Foo", "Alice"), (21, "bar", NULL, NULL, "Bar", "Bob");
\ No newline at end of file
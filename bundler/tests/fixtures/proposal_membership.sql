@@ -1,3 +1,3 @@
 INSERT INTO
-    `ProposalHasPerson` (`proposalId`, `personId`)
-VALUES (30, 20), (31, 20), (32, 20), (30, 21);
\ No newline at end of file
+    `ProposalHasPerson` (`proposalId`, `personId`, `role`)
+VALUES (30, 20, "Principal Investigator"), (31, 20, "Co-Investigator"), (32, 20, NULL), (30, 21, "Co-Investigator");
\ No newline at end of file
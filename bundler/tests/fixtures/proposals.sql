@@ -1,7 +1,10 @@
 INSERT INTO
     `Proposal` (
         `proposalId`,
+        `proposalCode`,
         `proposalNumber`,
+        `title`,
+        `state`,
         `externalId`
     )
-VALUES (30, "10030", '272E'), (31, "10031", '272F'), (32, "10032", '2730')
\ No newline at end of file
+VALUES (30, "cm", "10030", "Tomography of battery electrodes", "Open", '272E'), (31, "mx", "10031", "Structures of membrane proteins", "Closed", '272F'), (32, "sw", "10032", NULL, "Cancelled", '2730')
\ No newline at end of file
@@ -1,3 +1,3 @@
 INSERT INTO
-    `Session_has_Person` (`sessionId`, `personId`)
-VALUES (40, 20), (41, 20), (43, 21);
\ No newline at end of file
+    `Session_has_Person` (`sessionId`, `personId`, `role`)
+VALUES (40, 20, "Principal Investigator"), (41, 20, NULL), (43, 21, "Local Contact");
\ No newline at end of file
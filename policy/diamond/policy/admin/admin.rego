@@ -17,9 +17,9 @@ beamline_admin_for_subject[subject_name] contains beamline if {
 	some beamline in role_beamlines
 }
 
-admin := is_admin(token.claims.fedid) # regal ignore:rule-name-repeats-package
+admin := is_admin(token.subject) # regal ignore:rule-name-repeats-package
 
-beamline_admin := input.beamline in object.get(beamline_admin_for_subject, token.claims.fedid, [])
+beamline_admin := input.beamline in object.get(beamline_admin_for_subject, token.subject, [])
 
 # Users can change configuration for a beamline if they are either an admin for that beamline or a super admin
 default configure_beamline := false
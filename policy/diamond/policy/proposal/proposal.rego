@@ -18,6 +18,6 @@ access_proposal(subject, proposal_number) if admin.is_admin(subject) # regal ign
 # Allow if subject is on proposal
 access_proposal(subject, proposal_number) if on_proposal(subject, proposal_number)
 
-access := access_proposal(token.claims.fedid, input.proposal)
+access := access_proposal(token.subject, input.proposal)
 
-named_user := on_proposal(token.claims.fedid, input.proposal)
+named_user := on_proposal(token.subject, input.proposal)
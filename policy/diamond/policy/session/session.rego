@@ -40,9 +40,9 @@ access_session(subject, proposal_number, visit_number) if on_session(subject, pr
 
 # Rules depending on input data
 
-access := access_session(token.claims.fedid, input.proposal, input.visit)
+access := access_session(token.subject, input.proposal, input.visit)
 
-named_user := on_session(token.claims.fedid, input.proposal, input.visit)
+named_user := on_session(token.subject, input.proposal, input.visit)
 
 beamline := beamline_for(input.proposal, input.visit)
 
@@ -44,27 +44,27 @@ user_session := format_int(_session, 10) if {
 default modify_session := false
 
 modify_session if session.access_session(
-	token.claims.fedid,
+	token.subject,
 	data.diamond.data.sessions[input.session].proposal_number,
 	data.diamond.data.sessions[input.session].visit_number,
 )
 
 # service account check
 modify_session if {
-	not token.claims.fedid
+	not token.subject
 	session.beamline_for(
 		data.diamond.data.sessions[input.session].proposal_number,
 		data.diamond.data.sessions[input.session].visit_number,
 	) == token.claims.beamline
 }
 
-subject := data.diamond.data.subjects[token.claims.fedid]
+subject := data.diamond.data.subjects[token.subject]
 
 # Identifies all beamlines the subject is authorized to access
 # based on their assigned permissions.
 beamlines contains beamline if {
-	token.claims.fedid
-	not admin.is_admin(token.claims.fedid)
+	token.subject
+	not admin.is_admin(token.subject)
 	some p in subject.permissions
 	some beamline in object.get(data.diamond.data.admin, p, [])
 }
@@ -78,28 +78,27 @@ beamlines contains beamline if {
 # 3. Access via proposal-level permissions
 user_sessions contains "*" if {
 	subject
-	admin.is_admin(token.claims.fedid)
+	admin.is_admin(token.subject)
 }
 
 user_sessions contains format_int(session, 10) if {
 	subject
-	not admin.is_admin(token.claims.fedid)
+	not admin.is_admin(token.subject)
 	some session in subject.sessions
 }
 
 user_sessions contains format_int(session, 10) if {
 	subject
-	not admin.is_admin(token.claims.fedid)
+	not admin.is_admin(token.subject)
 	some beamline in beamlines
 	some session in data.diamond.data.beamlines[beamline].sessions
 }
 
 user_sessions contains format_int(session, 10) if {
 	subject
-	not admin.is_admin(token.claims.fedid)
+	not admin.is_admin(token.subject)
 	some p in subject.proposals
-	some i in data.diamond.data.proposals[format_int(p, 10)]
-	some session in i
+	some session in data.diamond.data.proposals[format_int(p, 10)].sessions
 }
 
 # service account check
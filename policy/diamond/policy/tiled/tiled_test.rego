@@ -77,14 +77,20 @@ diamond_data := {
 		},
 	},
 	"proposals": {
-		"1": {"sessions": {
-			"1": 11,
-			"2": 12,
-		}},
-		"2": {"sessions": {
-			"1": 13,
-			"2": 14,
-		}},
+		"1": {
+			"proposal_code": "cm",
+			"sessions": {
+				"1": 11,
+				"2": 12,
+			},
+		},
+		"2": {
+			"proposal_code": "mx",
+			"sessions": {
+				"1": 13,
+				"2": 14,
+			},
+		},
 	},
 	"beamlines": {"i03": {"sessions": [11]}, "b07": {"sessions": [12, 13, 14]}},
 	"admin": {"b07_admin": ["b07"]},
@@ -36,3 +36,10 @@ verified := io.jwt.decode_verify(input.token, {
 })
 
 claims := verified[2] if verified[0]
+
+# The subject identified by the token, by its fedid claim or otherwise by an alias such as its email
+subject := claims.fedid
+
+subject := data.diamond.data.aliases[lower(claims.email)] if { # regal ignore:external-reference
+	not claims.fedid
+}
@@ -0,0 +1,29 @@
+package diamond.policy.token_test
+
+import data.diamond.policy.token
+import rego.v1
+
+diamond_data := {"aliases": {
+	"alice@example.ac.uk": "alice",
+	"00000000000000000000000000000014": "alice",
+}}
+
+test_subject_from_fedid if {
+	token.subject == "bob" with data.diamond.data as diamond_data
+		with data.diamond.policy.token.claims as {"fedid": "bob", "email": "alice@example.ac.uk"}
+}
+
+test_subject_from_email_alias if {
+	token.subject == "alice" with data.diamond.data as diamond_data
+		with data.diamond.policy.token.claims as {"email": "Alice@Example.ac.uk"}
+}
+
+test_no_subject_for_unknown_email if {
+	not token.subject with data.diamond.data as diamond_data
+		with data.diamond.policy.token.claims as {"email": "oscar@example.ac.uk"}
+}
+
+test_no_subject_without_claims if {
+	not token.subject with data.diamond.data as diamond_data
+		with data.diamond.policy.token.claims as {}
+}